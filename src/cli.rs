@@ -1,3 +1,6 @@
+use crate::filters::ghosting::parse_persistence;
+use crate::filters::upscale::Upscaler;
+
 const TEST_ALL_INSTRUCTIONS: &str = "./test-roms/blargg/cpu_instrs.gb";
 const TEST_CPU_1_PATH: &str = "./test-roms/blargg/01-special.gb";
 const TEST_CPU_2_PATH: &str = "./test-roms/blargg/02-interrupts.gb";
@@ -14,7 +17,7 @@ const TEST_CPU_11_PATH: &str = "./test-roms/blargg/11-op a,(hl).gb";
 const TEST_INSTR_TIMING_PATH: &str = "./test-roms/blargg/instr_timing.gb";
 const TEST_MEM_TIMING_PATH: &str = "./test-roms/blargg/mem_timing.gb";
 
-const DMG_ACID_PATH: &str = "./test-roms/dmg-acid2.gb";
+pub const DMG_ACID_PATH: &str = "./test-roms/dmg-acid2.gb";
 
 const TETRIS_ROM_PATH: &str = "./roms/tetris.gb";
 const DEFAULT_ROM_PATH: &str = TETRIS_ROM_PATH;
@@ -23,23 +26,114 @@ pub enum Command {
     // Test(String),
     Rom(String),
     Debug(String),
+    Gdb(String),
+    /// Two Game Boys, linked together
+    Linked([String; 2]),
+}
+
+/// Options can appear anywhere on the command line, as `--name value` (or just `--name` for flags).
+#[derive(Default)]
+pub struct Options {
+    /// See the logging module for the format
+    pub log_levels: Option<String>,
+    /// The port the GDB stub listens on
+    pub gdb_port: Option<u16>,
+    /// Where to write the profiler's report, profiling is off without it
+    pub profile: Option<String>,
+    /// Where to save the Code/Data Log, logging is off without it
+    pub code_data_log: Option<String>,
+    /// Debugger commands to run on startup
+    pub debug_script: Option<String>,
+    /// The port (or address:port) to listen on for the other end of a link cable
+    pub link_host: Option<String>,
+    /// The address of the other end of a link cable, which is hosting it
+    pub link_connect: Option<String>,
+    /// Sync the link cable's ends in lockstep, so that sessions are deterministic
+    pub link_lockstep: bool,
+    /// Where the Game Boy Printer saves its prints, the printer is unplugged without it
+    pub printer: Option<String>,
+    /// How much to scale screenshots up by, they're saved at 160x144 without it
+    pub screenshot_scale: Option<usize>,
+    /// Record from the start, to a .gif or .y4m
+    pub record: Option<String>,
+    /// A palette preset's name, or a palette file
+    pub palette: Option<String>,
+    /// How much the window starts scaled up by
+    pub scale: Option<usize>,
+    /// How much of the previous frames to blend into each one, blending is off without it
+    pub ghosting: Option<f32>,
+    /// Which upscaler the screen starts with, it's left as it is without it
+    pub filter: Option<Upscaler>,
 }
 
-pub fn parse_cli_inputs() -> Command {
+pub fn parse_cli_inputs() -> (Command, Options) {
     let mut args: Vec<String> = std::env::args().collect();
+    args.remove(0); // Discard the first CLI arg (it's just the path to the executable)
+
+    let options = parse_options(&mut args);
+
     args.reverse(); // This way, the args can be popped from the back in order
-    args.pop(); // Discard the first CLI arg (it's just the path to the executable)
 
     let arg = args.pop();
     if arg.is_none() {
-        return Command::Rom(DEFAULT_ROM_PATH.to_string());
+        return (Command::Rom(DEFAULT_ROM_PATH.to_string()), options);
     }
 
-    match arg.unwrap().as_str() {
+    let command = match arg.unwrap().as_str() {
         "debug" => Command::Debug(parse_rom_arg(args)),
+        "gdb" => Command::Gdb(parse_rom_arg(args)),
+        "link" => Command::Linked(parse_linked_rom_args(args)),
         "rom" => Command::Rom(parse_rom_arg(args)),
         _ => Command::Rom(parse_rom_arg(args)),
+    };
+    (command, options)
+}
+
+/// Removes the options from args, leaving only the positional arguments.
+fn parse_options(args: &mut Vec<String>) -> Options {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+
+    let mut iter = args.drain(..);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--log" => options.log_levels = iter.next(),
+            "--profile" => options.profile = iter.next(),
+            "--cdl" => options.code_data_log = iter.next(),
+            "--debug-script" => options.debug_script = iter.next(),
+            "--link-host" => options.link_host = iter.next(),
+            "--link-connect" => options.link_connect = iter.next(),
+            "--link-lockstep" => options.link_lockstep = true,
+            "--printer" => options.printer = iter.next(),
+            "--record" => options.record = iter.next(),
+            "--palette" => options.palette = iter.next(),
+            "--ghosting" => match iter.next().as_deref().and_then(parse_persistence) {
+                Some(persistence) => options.ghosting = Some(persistence),
+                None => println!("Invalid ghosting persistence, it must be from 0 up to 1"),
+            },
+            "--filter" => match iter.next().as_deref().and_then(Upscaler::parse) {
+                Some(upscaler) => options.filter = Some(upscaler),
+                None => println!("Invalid filter, it must be one of {}", Upscaler::names()),
+            },
+            "--scale" => match iter.next().map(|scale| scale.parse()) {
+                Some(Ok(scale)) if scale > 0 => options.scale = Some(scale),
+                _ => println!("Invalid scale"),
+            },
+            "--screenshot-scale" => match iter.next().map(|scale| scale.parse()) {
+                Some(Ok(scale)) if scale > 0 => options.screenshot_scale = Some(scale),
+                _ => println!("Invalid screenshot scale"),
+            },
+            "--gdb-port" => match iter.next().map(|port| port.parse()) {
+                Some(Ok(port)) => options.gdb_port = Some(port),
+                _ => println!("Invalid GDB port"),
+            },
+            _ => positional.push(arg),
+        }
     }
+    drop(iter);
+
+    *args = positional;
+    options
 }
 
 fn parse_rom_arg(mut args: Vec<String>) -> String {
@@ -51,6 +145,16 @@ fn parse_rom_arg(mut args: Vec<String>) -> String {
     map_rom_name_to_path(&arg.unwrap())
 }
 
+/// The second Game Boy runs the same ROM as the first, unless it's given one.
+fn parse_linked_rom_args(mut args: Vec<String>) -> [String; 2] {
+    let first = map_rom_name_to_path(&args.pop().unwrap_or_default());
+    let second = match args.pop() {
+        Some(name) => map_rom_name_to_path(&name),
+        None => first.clone(),
+    };
+    [first, second]
+}
+
 fn map_rom_name_to_path(name: &str) -> String {
     match name {
         "testall" => TEST_ALL_INSTRUCTIONS,
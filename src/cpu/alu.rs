@@ -21,7 +21,7 @@ pub enum AluUnary {
     Dec,
 }
 
-impl Cpu {
+impl<B: Bus> Cpu<B> {
     // These functions map the most common ALU operations to their functions.
     // This one is for binary operations:
     fn alu_a_u8(&mut self, op: AluBinary, value: u8) {
@@ -80,8 +80,16 @@ impl Cpu {
     }
 
     pub fn alu_a_n8(&mut self, op: AluBinary) {
-        let value = self.fetch_byte();
-        self.alu_a_u8(op, value);
+        match self.instruction_m_cycles_remaining {
+            // Fetch
+            2 => (),
+            // Read n8 and op on A with it
+            1 => {
+                let value = self.fetch_byte();
+                self.alu_a_u8(op, value);
+            }
+            _ => unreachable!(),
+        }
     }
 
     pub fn alu_r8(&mut self, op: AluUnary, r8: R8) {
@@ -108,6 +116,16 @@ impl Cpu {
 
     // 16-bit
     pub fn add_hl_r16(&mut self, r16: R16) {
+        match self.instruction_m_cycles_remaining {
+            // Fetch
+            2 => (),
+            // Internal
+            1 => self.add_hl_u16(r16),
+            _ => unreachable!(),
+        }
+    }
+
+    fn add_hl_u16(&mut self, r16: R16) {
         let hl = self.reg.get16(R16::HL);
         let value = self.reg.get16(r16);
 
@@ -276,10 +294,18 @@ impl Cpu {
 
     // 16-bit
     pub fn inc_r16(&mut self, r16: R16) {
-        let value = self.reg.get16(r16);
-        let result = value.wrapping_add(1);
+        match self.instruction_m_cycles_remaining {
+            // Fetch
+            2 => (),
+            // Internal
+            1 => {
+                let value = self.reg.get16(r16);
+                let result = value.wrapping_add(1);
 
-        self.reg.set16(r16, result);
+                self.reg.set16(r16, result);
+            }
+            _ => unreachable!(),
+        }
     }
 
     // ----- DEC -----
@@ -295,10 +321,18 @@ impl Cpu {
 
     // 16-bit
     pub fn dec_r16(&mut self, r16: R16) {
-        let value = self.reg.get16(r16);
-        let result = value.wrapping_sub(1);
+        match self.instruction_m_cycles_remaining {
+            // Fetch
+            2 => (),
+            // Internal
+            1 => {
+                let value = self.reg.get16(r16);
+                let result = value.wrapping_sub(1);
 
-        self.reg.set16(r16, result);
+                self.reg.set16(r16, result);
+            }
+            _ => unreachable!(),
+        }
     }
 
     // ----- CPL -----
@@ -17,7 +17,7 @@ pub enum BitflagOp {
     Set,
 }
 
-impl Cpu {
+impl<B: Bus> Cpu<B> {
     // These functions are the public interface for all bitops
     pub fn bitshift_r8(&mut self, op: BitshiftOp, r8: R8) {
         let bits = self.reg.get(r8);
@@ -0,0 +1,119 @@
+//! The shadow call stack keeps track of CALL/RST/interrupt entries and RET/RETI exits,
+//! independently of the real stack in memory. It exists for the debugger's benefit,
+//! since games are free to use the stack however they like.
+
+use super::*;
+use crate::mmu::memmap::map_bank;
+
+/// Games that manipulate the stack directly can leave frames that are never returned from.
+/// The oldest frames are dropped past this depth, so the stack can't grow forever.
+const MAX_CALL_STACK_DEPTH: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CallKind {
+    Call,
+    Rst,
+    Interrupt,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CallFrame {
+    pub kind: CallKind,
+    /// The address of the CALL/RST, or the address that was interrupted
+    pub caller_pc: u16,
+    pub caller_bank: u8,
+    pub target: u16,
+    /// Where the return address was pushed to
+    pub sp: u16,
+}
+
+impl<B: Bus> Cpu<B> {
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.call_stack
+    }
+
+    /// Called once the return address has been pushed, and PC points at the target.
+    fn push_call_frame(&mut self, kind: CallKind, caller_pc: u16) {
+        if self.call_stack.len() == MAX_CALL_STACK_DEPTH {
+            self.call_stack.remove(0);
+        }
+
+        self.call_stack.push(CallFrame {
+            kind,
+            caller_pc,
+            caller_bank: map_bank(caller_pc),
+            target: self.reg.get16(R16::PC),
+            sp: self.reg.get16(R16::SP),
+        });
+    }
+
+    pub fn push_call_frame_for_instruction(&mut self) {
+        let kind = match self.current_instruction {
+            0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => CallKind::Call,
+            _ => CallKind::Rst,
+        };
+        self.push_call_frame(kind, self.current_instruction_addr);
+    }
+
+    pub fn push_call_frame_for_interrupt(&mut self, interrupted_pc: u16) {
+        self.push_call_frame(CallKind::Interrupt, interrupted_pc);
+    }
+
+    /// RET pops the frame whose return address was just read from the stack.
+    /// Any frames deeper than that were abandoned, so they get popped too.
+    pub fn pop_call_frames(&mut self, return_addr_sp: u16) {
+        while let Some(frame) = self.call_stack.last() {
+            if frame.sp > return_addr_sp {
+                break;
+            }
+            self.call_stack.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::tests::TestBus;
+
+    fn run_instruction(cpu: &mut Cpu<TestBus>) {
+        loop {
+            cpu.tick();
+            if cpu.at_instruction_boundary() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_call_stack() {
+        // CALL $C100
+        let (bus, mut cpu) = TestBus::cpu_with_program(0xC000, &[0xCD, 0x00, 0xC1]);
+        cpu.reg.set16(R16::SP, 0xD000);
+        // RST $08, RET
+        bus.borrow_mut().memory[0xC100..0xC102].copy_from_slice(&[0xCF, 0xC9]);
+        // RET
+        bus.borrow_mut().memory[0x0008] = 0xC9;
+
+        run_instruction(&mut cpu);
+        run_instruction(&mut cpu);
+        let frames = cpu.call_stack();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            (frames[0].kind, frames[0].caller_pc, frames[0].target),
+            (CallKind::Call, 0xC000, 0xC100)
+        );
+        assert_eq!(
+            (frames[1].kind, frames[1].caller_pc, frames[1].target),
+            (CallKind::Rst, 0xC100, 0x0008)
+        );
+
+        // RET from the RST, then from the CALL
+        run_instruction(&mut cpu);
+        assert_eq!(cpu.call_stack().len(), 1);
+        assert_eq!(cpu.reg.get16(R16::PC), 0xC101);
+        run_instruction(&mut cpu);
+        assert!(cpu.call_stack().is_empty());
+        assert_eq!(cpu.reg.get16(R16::PC), 0xC003);
+    }
+}
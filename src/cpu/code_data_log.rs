@@ -0,0 +1,159 @@
+//! The Code/Data Logger records how the CPU used each byte of memory: ROM bytes can be
+//! executed as an opcode, read as an operand, or read as data, and RAM bytes can be read
+//! and written. This is what a disassembler needs to tell code apart from data in a ROM
+//! it has no symbols for.
+//!
+//! The ROM log is saved as one byte per ROM byte, in ROM file order. Bits 0 and 1 mark code
+//! and data, the same as the CDL files from FCEUX and Mesen, so tools built around those can
+//! read it. Bit 7 additionally marks opcodes (the first byte of each instruction).
+//! The RAM log covers 8000-FFFF and is saved next to it, with bit 0 for reads and bit 1 for writes.
+
+use super::*;
+use crate::mmu::memmap::map_bank;
+use std::{io, path::Path};
+
+pub const CDL_CODE: u8 = 0x01;
+pub const CDL_DATA: u8 = 0x02;
+pub const CDL_OPCODE: u8 = 0x80;
+
+pub const CDL_RAM_READ: u8 = 0x01;
+pub const CDL_RAM_WRITE: u8 = 0x02;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_START_ADDR: u16 = 0x8000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Opcode,
+    Operand,
+    Read,
+    Write,
+}
+
+pub struct CodeDataLog {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl CodeDataLog {
+    pub fn new() -> Self {
+        CodeDataLog {
+            // Grows as higher banks are accessed
+            rom: vec![0; 2 * ROM_BANK_SIZE],
+            ram: vec![0; (u16::MAX - RAM_START_ADDR) as usize + 1],
+        }
+    }
+
+    pub fn record(&mut self, addr: u16, access: Access) {
+        if addr >= RAM_START_ADDR {
+            let flags = match access {
+                Access::Write => CDL_RAM_WRITE,
+                _ => CDL_RAM_READ,
+            };
+            self.ram[(addr - RAM_START_ADDR) as usize] |= flags;
+            return;
+        }
+
+        let flags = match access {
+            Access::Opcode => CDL_CODE | CDL_OPCODE,
+            Access::Operand => CDL_CODE,
+            Access::Read => CDL_DATA,
+            // Writes to ROM go to the cartridge's registers, not the ROM itself
+            Access::Write => return,
+        };
+        let offset = rom_offset(addr);
+        if offset >= self.rom.len() {
+            self.rom.resize(offset.next_multiple_of(ROM_BANK_SIZE), 0);
+        }
+        self.rom[offset] |= flags;
+    }
+
+    /// Writes the ROM log to the path, and the RAM log next to it (`game.cdl` -> `game.ram.cdl`).
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, &self.rom)?;
+        std::fs::write(path.with_extension("ram.cdl"), &self.ram)
+    }
+}
+
+// Only the tests look at individual flags, tools read the saved files
+#[cfg(test)]
+impl CodeDataLog {
+    pub fn get_rom_flags(&self, offset: usize) -> u8 {
+        self.rom.get(offset).copied().unwrap_or(0)
+    }
+
+    pub fn get_ram_flags(&self, addr: u16) -> u8 {
+        match addr.checked_sub(RAM_START_ADDR) {
+            Some(index) => self.ram[index as usize],
+            None => 0,
+        }
+    }
+}
+
+/// Where an address in the ROM area is in the ROM file, given the bank that's mapped in.
+fn rom_offset(addr: u16) -> usize {
+    match addr as usize {
+        addr if addr < ROM_BANK_SIZE => addr,
+        addr => map_bank(addr as u16) as usize * ROM_BANK_SIZE + (addr - ROM_BANK_SIZE),
+    }
+}
+
+impl<B: Bus> Cpu<B> {
+    pub fn enable_code_data_log(&mut self) {
+        self.code_data_log = Some(RefCell::new(CodeDataLog::new()));
+    }
+
+    pub fn code_data_log(&self) -> Option<std::cell::Ref<'_, CodeDataLog>> {
+        self.code_data_log.as_ref().map(RefCell::borrow)
+    }
+
+    pub fn log_access(&self, addr: u16, access: Access) {
+        if let Some(log) = &self.code_data_log {
+            log.borrow_mut().record(addr, access);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::tests::TestBus;
+
+    #[test]
+    fn test_record() {
+        let mut log = CodeDataLog::new();
+        log.record(0x0150, Access::Opcode);
+        log.record(0x0151, Access::Operand);
+        log.record(0x0151, Access::Read);
+        log.record(0x4000, Access::Read);
+        log.record(0x2000, Access::Write);
+        log.record(0xC000, Access::Write);
+        log.record(0xFF80, Access::Read);
+
+        assert_eq!(log.get_rom_flags(0x0150), CDL_CODE | CDL_OPCODE);
+        assert_eq!(log.get_rom_flags(0x0151), CDL_CODE | CDL_DATA);
+        assert_eq!(log.get_rom_flags(0x4000), CDL_DATA);
+        assert_eq!(log.get_rom_flags(0x2000), 0);
+        assert_eq!(log.get_ram_flags(0xC000), CDL_RAM_WRITE);
+        assert_eq!(log.get_ram_flags(0xFF80), CDL_RAM_READ);
+    }
+
+    #[test]
+    fn test_cpu_accesses() {
+        // LD A, [$0200], LD [$C000], A
+        let program = [0xFA, 0x00, 0x02, 0xEA, 0x00, 0xC0];
+        let (_bus, mut cpu) = TestBus::cpu_with_program(0x0150, &program);
+        cpu.enable_code_data_log();
+
+        for _t_cycle in 0..8 * M_CYCLE_DURATION {
+            cpu.tick();
+        }
+
+        let log = cpu.code_data_log().unwrap();
+        assert_eq!(log.get_rom_flags(0x0150), CDL_CODE | CDL_OPCODE);
+        assert_eq!(log.get_rom_flags(0x0151), CDL_CODE);
+        assert_eq!(log.get_rom_flags(0x0153), CDL_CODE | CDL_OPCODE);
+        assert_eq!(log.get_rom_flags(0x0200), CDL_DATA);
+        assert_eq!(log.get_ram_flags(0xC000), CDL_RAM_WRITE);
+    }
+}
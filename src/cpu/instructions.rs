@@ -1,9 +1,10 @@
 use super::*;
 
-impl Cpu {
+impl<B: Bus> Cpu<B> {
 pub fn execute(&mut self) {
         // todo! Move this under "Fetch instruction"
         let instruction = if self.instruction_t_cycles_remaining == 0 {
+            self.current_instruction_addr = self.reg.get16(R16::PC);
             let opcode = self.fetch_instruction();
             self.prev_instruction = self.current_instruction;
             self.current_instruction = opcode;
@@ -12,61 +13,18 @@ pub fn execute(&mut self) {
             self.instruction_m_cycles_remaining = self.instruction_t_cycles_remaining / 4;
             opcode
         } else {
-            match self.current_instruction {
-                // This part will only be reached for multi-step instructions.
-                // By the time all of them are implemented, this match won't be needed.
-                // This is just to keep unimplemented multi-steps from breaking
-                // by executing multiple times.
-                // Also just for keeping track of which ones I've implemented so far.
-                // 
-                // Eventually, ALL multi-cycle instructions should handle each cycle
-                // explicitly.
-                0xC5 | 0xD5 | 0xE5 | 0xF5  // PUSH
-                | 0xC1 | 0xD1 | 0xE1 | 0xF1 // POP
-                | 0xE8 // ADD SP, e8
-                | 0xF8 // LD HL, SP + e8
-                | 0xC3 // JP a16
-                | 0xC2 | 0xD2 | 0xCA | 0xDA // JP CC, a16
-                | 0xC7 | 0xD7 | 0xE7 | 0xF7 | 0xCF | 0xDF | 0xEF | 0xFF // RST VEC
-                | 0xCD // CALL u16
-                | 0xC4 | 0xD4 | 0xCC | 0xDC // CALL CC, u16
-                | 0xC9 // RET
-                | 0xD9 // RETI
-                | 0xC0 | 0xD0 | 0xC8 | 0xD8 // RET CC
-                | 0x70 | 0x71 | 0x72 | 0x73 | 0x74 | 0x75 | 0x77 // LC [HL], r8
-                | 0x46 | 0x56 | 0x66 | 0x4E | 0x5E | 0x6E | 0x7E // LC r8, [HL]
-                | 0x36 // LD [HL], n8
-                | 0x06 | 0x16 | 0x26 | 0x0E | 0x1E | 0x2E | 0x3E // LD r8, n8
-                | 0x86 | 0x96 | 0xA6 | 0xB6 | 0x8E | 0x9E | 0xAE | 0xBE // ALU A, [HL]
-                | 0xF2 // LD A, [C]
-                | 0xE2 // LD [C], A
-                | 0x02 | 0x12 // LD [r16], A
-                | 0x0A | 0x1A // LD A, [r16]
-                | 0xEA // LD [a16], A
-                | 0xFA // LD A, [a16]
-                | 0xE0 // LDH [a8], A
-                | 0xF0 // LDH A, [a8]
-                | 0x22 // LD [HL+], A
-                | 0x32 // LD [HL-], A
-                | 0x2A // LD A, [HL+]
-                | 0x3A // LD A, [HL-]
-                | 0x34 // INC [HL]
-                | 0x35 // DEC [HL]
-                => self.current_instruction, 
-                _ => 0x00, // Default to no-ops for unimplemented multi-step instructions
-            }
+            // Multi-cycle instructions handle each of their m-cycles explicitly
+            self.current_instruction
         };
 
-        let pc = self.reg.get16(R16::PC);
-        let sp = self.reg.get16(R16::SP);
-        if pc == 0x0100 {
-            println!("RESET");
-        }
-
-        // println!(
-        //     "OP: {:02x}, PC: {:04x}, SP: {:04x} CYCLE: {}",
-        //     self.current_instruction, pc, sp, self.instruction_m_cycles_remaining
-        // );
+        trace!(
+            target: CPU,
+            "OP: {:02x}, PC: {:04x}, SP: {:04x} CYCLE: {}",
+            self.current_instruction,
+            self.reg.get16(R16::PC),
+            self.reg.get16(R16::SP),
+            self.instruction_m_cycles_remaining
+        );
 
         match instruction {
             0x00 => (),                                // NOP
@@ -638,4 +596,26 @@ pub fn execute(&mut self) {
             0xFF => self.bitflag_u3_r8(BitflagOp::Set, 7, R8::A), // SET 7, A
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::tests::TestBus;
+
+    #[test]
+    fn test_instruction_boundaries() {
+        // NOP, LD B, n8, CB: SWAP A, NOP
+        let program = [0x00, 0x06, 0x42, 0xCB, 0x37, 0x00];
+        let (_bus, mut cpu) = TestBus::cpu_with_program(0xC000, &program);
+
+        let mut boundaries = Vec::new();
+        for _t_cycle in 0..6 * M_CYCLE_DURATION {
+            if cpu.at_instruction_boundary() {
+                boundaries.push(cpu.reg.get16(R16::PC));
+            }
+            cpu.tick();
+        }
+        assert_eq!(boundaries, vec![0xC000, 0xC001, 0xC003, 0xC005]);
+    }
+}
@@ -2,18 +2,21 @@ use super::*;
 /// the first three bits of the interrupt registers are invalid.
 /// Therefore, they should not be checked when handling interrupts.
 const INTERRUPT_MASK: u8 = 0x1F;
+/// The lower nibble of P1 reads low for each selected button that is held.
+const JOYPAD_INPUT_MASK: u8 = 0x0F;
 
 pub enum Interrupt {}
 
-impl Cpu {
+impl<B: Bus> Cpu<B> {
     pub fn update_interrupt_status(&mut self) {
         // Interrupts cannot be triggered in the middle of instructions.
-        if self.instruction_m_cycles_remaining > 1 {
+        // This includes the gap between a CB prefix and the opcode that follows it.
+        if self.instruction_t_cycles_remaining != 0 || self.current_instruction_is_prefixed {
             return;
         }
 
-        let ie_byte = self.read_byte(IE_ADDR);
-        let if_byte = self.read_byte(IF_ADDR);
+        let ie_byte = self.read_byte_override(IE_ADDR);
+        let if_byte = self.read_byte_override(IF_ADDR);
         let interrupts_are_pending = (ie_byte & if_byte & INTERRUPT_MASK) != 0;
 
         if !interrupts_are_pending {
@@ -41,16 +44,12 @@ impl Cpu {
         // Interrupts are prioritized in order of their bit position (bit 0 first, bit 4 last)
         if vblank_interrupt && vblank_interrupt_enabled {
             self.start_interrupt(VBLANK_INTERRUPT_HANDLER_ADDR, VBLANK_INTERRUPT_BIT);
-            // println!("VBLANK INTERRUPT");
         } else if stat_interrupt && stat_interrupt_enabled {
             self.start_interrupt(STAT_INTERRUPT_HANDLER_ADDR, STAT_INTERRUPT_BIT);
-            // println!("STAT INTERRUPT");
         } else if timer_interrupt && timer_interrupt_enabled {
             self.start_interrupt(TIMER_INTERRUPT_HANDLER_ADDR, TIMER_INTERRUPT_BIT);
-            // println!("TIMER INTERRUPT");
         } else if serial_interrupt && serial_interrupt_enabled {
             self.start_interrupt(SERIAL_INTERRUPT_HANDLER_ADDR, SERIAL_INTERRUPT_BIT);
-            // println!("SERIAL INTERRUPT");
         } else if joypad_interrupt && joypad_interrupt_enabled {
             self.start_interrupt(JOYPAD_INTERRUPT_HANDLER_ADDR, JOYPAD_INTERRUPT_BIT);
         }
@@ -63,6 +62,12 @@ impl Cpu {
         self.current_interrupt_handler_addr = interrupt_handler_addr;
         self.current_interrupt_bit = interrupt_bit;
         self.handling_interrupt = true;
+        debug!(
+            target: INTERRUPTS,
+            "Interrupt {} requested at PC: {:04x}",
+            interrupt_bit,
+            self.reg.get16(R16::PC)
+        );
     }
 
     pub fn step_interrupt(&mut self) {
@@ -91,7 +96,6 @@ impl Cpu {
                     set_bit(&mut if_byte, self.current_interrupt_bit, false);
                     self.write_byte(IF_ADDR, if_byte);
                 }
-                println!("3");
             }
             // Push PC low byte
             2 => {
@@ -101,13 +105,18 @@ impl Cpu {
 
                 let low_byte = self.reg.get16(R16::PC) as u8;
                 self.write_byte(sp, low_byte);
-                println!("2");
             }
             // Jump to interrupt handler address
             1 => {
+                let interrupted_pc = self.reg.get16(R16::PC);
                 self.jp_u16(self.current_interrupt_handler_addr);
+                self.push_call_frame_for_interrupt(interrupted_pc);
                 self.handling_interrupt = false;
-                println!("1");
+                trace!(
+                    target: INTERRUPTS,
+                    "Jumped to interrupt handler at {:04x}",
+                    self.current_interrupt_handler_addr
+                );
             }
             _ => unreachable!("{}", self.interrupt_t_cycles_remaining),
         }
@@ -123,15 +132,169 @@ impl Cpu {
         self.ime_pending = true;
     }
 
-    // todo!
-    pub fn stop(&mut self) {}
+    /// STOP is a 1 or 2-byte opcode that can enter STOP mode, enter HALT mode, or do nothing,
+    /// depending on whether a button is held and whether an interrupt is pending.
+    /// [Pan Docs](https://gbdev.io/pandocs/Reducing_Power_Consumption.html#using-the-stop-instruction)
+    pub fn stop(&mut self) {
+        let button_held = self.joypad_input_held();
+        let interrupts_are_pending = self.interrupts_are_pending();
+
+        match (button_held, interrupts_are_pending) {
+            // 1-byte opcode, nothing happens
+            (true, true) => (),
+            // 2-byte opcode, enter HALT mode
+            (true, false) => {
+                self.skip_byte();
+                self.halted = true;
+                debug!(target: CPU, "STOP entered HALT mode");
+            }
+            // 1-byte opcode, enter STOP mode
+            (false, true) => self.enter_stop_mode(),
+            // 2-byte opcode, enter STOP mode
+            (false, false) => {
+                self.skip_byte();
+                self.enter_stop_mode();
+            }
+        }
+    }
+
+    /// STOP mode halts the system clock, which resets DIV. The LCD is blanked until
+    /// the CPU is woken back up by a button press.
+    fn enter_stop_mode(&mut self) {
+        debug!(target: CPU, "Entered STOP mode");
+        self.stopped = true;
+        // The reset isn't a store by the program, so it's neither logged nor watched
+        self.mmu.borrow_mut().write_byte(DIV_ADDR, 0);
+    }
+
+    /// Unlike HALT, STOP mode can only be exited by pressing a selected button.
+    /// Returns true if the CPU is still stopped.
+    pub fn update_stop_status(&mut self) -> bool {
+        if self.stopped && self.joypad_input_held() {
+            debug!(target: CPU, "Woke up from STOP mode");
+            self.stopped = false;
+        }
+        self.stopped
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Returns IME, and whether an EI is still waiting to set it.
+    pub fn get_ime(&self) -> (bool, bool) {
+        (self.ime, self.ime_pending)
+    }
+
+    fn joypad_input_held(&self) -> bool {
+        (self.read_byte_override(P1_ADDR) & JOYPAD_INPUT_MASK) != JOYPAD_INPUT_MASK
+    }
+
+    fn interrupts_are_pending(&self) -> bool {
+        let ie_byte = self.read_byte_override(IE_ADDR);
+        let if_byte = self.read_byte_override(IF_ADDR);
+        (ie_byte & if_byte & INTERRUPT_MASK) != 0
+    }
+
+    // The second byte of STOP is ignored
+    fn skip_byte(&mut self) {
+        let pc = self.reg.get16(R16::PC);
+        self.reg.set16(R16::PC, pc.wrapping_add(1));
+    }
 
     pub fn halt(&mut self) {
-        let ie_byte = self.read_byte(IE_ADDR);
-        let if_byte = self.read_byte(IF_ADDR);
-        let interrupts_are_pending = (ie_byte & if_byte & INTERRUPT_MASK) != 0;
+        let interrupts_are_pending = self.interrupts_are_pending();
 
         self.halted = !(self.ime && interrupts_are_pending);
         self.halt_bug_active = !self.ime && interrupts_are_pending;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::code_data_log::CDL_RAM_WRITE;
+    use crate::cpu::tests::{BusAccess, TestBus};
+
+    #[test]
+    fn test_interrupt_waits_for_instruction_to_finish() {
+        // LD B, n8
+        let (bus, mut cpu) = TestBus::cpu_with_program(0xC000, &[0x06, 0x42]);
+        cpu.reg.set16(R16::SP, 0xD000);
+
+        // Request a VBlank interrupt partway through the instruction
+        for _t_cycle in 0..M_CYCLE_DURATION {
+            cpu.tick();
+        }
+        bus.borrow_mut().memory[IE_ADDR as usize] = 1 << VBLANK_INTERRUPT_BIT;
+        bus.borrow_mut().memory[IF_ADDR as usize] = 1 << VBLANK_INTERRUPT_BIT;
+
+        // Finish the instruction, then dispatch the interrupt (5 m-cycles)
+        for _t_cycle in 0..6 * M_CYCLE_DURATION {
+            cpu.tick();
+        }
+
+        assert_eq!(cpu.reg.get(R8::B), 0x42);
+        assert_eq!(cpu.reg.get16(R16::PC), VBLANK_INTERRUPT_HANDLER_ADDR);
+        assert_eq!(bus.borrow().memory[0xCFFE], 0x02);
+        assert_eq!(bus.borrow().memory[0xCFFF], 0xC0);
+    }
+
+    #[test]
+    fn test_stop_waits_for_button_press() {
+        // STOP, followed by INC B
+        let (bus, mut cpu) = TestBus::cpu_with_program(0xC000, &[0x10, 0x00, 0x04]);
+        bus.borrow_mut().memory[P1_ADDR as usize] = 0xFF;
+
+        // With no button held and no interrupt pending, STOP is a 2-byte opcode
+        for _t_cycle in 0..4 * M_CYCLE_DURATION {
+            cpu.tick();
+        }
+        assert!(cpu.is_stopped());
+        assert_eq!(cpu.reg.get16(R16::PC), 0xC002);
+        assert_eq!(cpu.reg.get(R8::B), 0x00);
+
+        bus.borrow_mut().memory[P1_ADDR as usize] = 0xFE;
+        for _t_cycle in 0..2 * M_CYCLE_DURATION {
+            cpu.tick();
+        }
+        assert!(!cpu.is_stopped());
+        assert_eq!(cpu.reg.get(R8::B), 0x01);
+    }
+
+    #[test]
+    fn test_stop_with_button_held_halts() {
+        let (bus, mut cpu) = TestBus::cpu_with_program(0xC000, &[0x10]);
+        bus.borrow_mut().memory[P1_ADDR as usize] = 0xFE;
+
+        for _t_cycle in 0..M_CYCLE_DURATION {
+            cpu.tick();
+        }
+        assert!(!cpu.is_stopped());
+        assert!(cpu.halted);
+        assert_eq!(cpu.reg.get16(R16::PC), 0xC002);
+    }
+
+    #[test]
+    fn test_stop_resets_div_without_a_cpu_write() {
+        let (bus, mut cpu) = TestBus::cpu_with_program(0xC000, &[0x10]);
+        cpu.enable_code_data_log();
+        bus.borrow_mut().memory[P1_ADDR as usize] = 0xFF;
+        bus.borrow_mut().memory[DIV_ADDR as usize] = 0xAB;
+
+        for _t_cycle in 0..M_CYCLE_DURATION {
+            cpu.tick();
+        }
+        assert!(cpu.is_stopped());
+        assert_eq!(bus.borrow().memory[DIV_ADDR as usize], 0x00);
+
+        let log = cpu.code_data_log().unwrap();
+        assert_eq!(log.get_ram_flags(DIV_ADDR) & CDL_RAM_WRITE, 0);
+        let watched = bus.borrow().take_watched_accesses();
+        assert!(!watched.contains(&BusAccess::Write(DIV_ADDR, 0x00)));
+    }
+}
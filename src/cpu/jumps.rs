@@ -6,7 +6,7 @@ const JP_CC_EXTRA_T_CYCLES: u8 = 4;
 const CALL_CC_EXTRA_T_CYCLES: u8 = 12;
 const RET_CC_EXTRA_T_CYCLES: u8 = CALL_CC_EXTRA_T_CYCLES;
 
-impl Cpu {
+impl<B: Bus> Cpu<B> {
     // JP
     pub fn jp_u16(&mut self, addr: u16) {
         self.reg.set16(R16::PC, addr);
@@ -67,17 +67,31 @@ impl Cpu {
     }
 
     pub fn jr_e8(&mut self) {
-        let byte = self.fetch_byte();
-        self.jr(byte);
+        match self.instruction_m_cycles_remaining {
+            // Fetch
+            3 => (),
+            // Read e8
+            2 => self.byte_buf = self.fetch_byte(),
+            // Internal / Jump
+            1 => self.jr(self.byte_buf),
+            _ => unreachable!(),
+        }
     }
 
     pub fn jr_cc_e8(&mut self, flag: Flag, expect: bool) {
-        let byte = self.fetch_byte();
-
-        if expect == self.reg.get_flag(flag) {
-            self.jr(byte);
-        } else {
-            self.instruction_t_cycles_remaining -= JP_CC_EXTRA_T_CYCLES;
+        match self.instruction_m_cycles_remaining {
+            // Fetch
+            3 => (),
+            // Read e8 and check condition
+            2 => {
+                self.byte_buf = self.fetch_byte();
+                if expect != self.reg.get_flag(flag) {
+                    self.instruction_t_cycles_remaining -= JP_CC_EXTRA_T_CYCLES;
+                }
+            }
+            // Internal / Jump
+            1 => self.jr(self.byte_buf),
+            _ => unreachable!(),
         }
     }
 
@@ -95,16 +109,11 @@ impl Cpu {
             1 => {
                 self.push_r16(R16::PC);
                 self.jp_u16(addr);
+                self.push_call_frame_for_instruction();
             }
             _ => unreachable!(),
         }
     }
-    
-    // Currently, this function is only used by interrupts.
-    pub fn rst_vec_instant(&mut self, addr: u16) {
-        self.push_r16_instant(R16::PC);
-        self.jp_u16(addr);
-    }
 
     pub fn call_a16(&mut self) {
         match self.instruction_m_cycles_remaining {
@@ -181,6 +190,9 @@ impl Cpu {
 
                 self.reg.set16_high(R16::PC, high_byte);
                 self.reg.set16(R16::SP, sp.wrapping_add(1));
+
+                // The low byte was read from the previous address
+                self.pop_call_frames(sp.wrapping_sub(1));
             }
             // Internal
             1 => (),
@@ -1,5 +1,5 @@
 use super::*;
-impl Cpu {
+impl<B: Bus> Cpu<B> {
     pub fn push_r16(&mut self, r16: R16) {
         match self.instruction_m_cycles_remaining {
             // Instruction decoding
@@ -27,18 +27,6 @@ impl Cpu {
         }
     }
 
-    // todo!
-    // This function will go away once all opcodes are m-cycle accurate
-    pub fn push_r16_instant(&mut self, r16: R16) {
-        // Decrement sp first
-        let sp = self.reg.get16(R16::SP).wrapping_sub(2);
-        self.reg.set16(R16::SP, sp);
-
-        // Push the byte next
-        let word = self.reg.get16(r16);
-        self.write_word(sp, word);
-    }
-
     pub fn pop_r16(&mut self, r16: R16) {
         match self.instruction_m_cycles_remaining {
             // Internal delay
@@ -82,8 +70,19 @@ impl Cpu {
     }
 
     pub fn ld_r16_n16(&mut self, r16: R16) {
-        let word = self.fetch_word();
-        self.reg.set16(r16, word);
+        match self.instruction_m_cycles_remaining {
+            // Fetch
+            3 => (),
+            // Read the lower byte
+            2 => self.word_buf_low = self.fetch_byte(),
+            // Read the upper byte, then load n16 into r16
+            1 => {
+                self.word_buf_high = self.fetch_byte();
+                let word = self.get_word_buf();
+                self.reg.set16(r16, word);
+            }
+            _ => unreachable!(),
+        }
     }
 
     pub fn ld_at_hl_r8(&mut self, r8: R8) {
@@ -329,9 +328,27 @@ impl Cpu {
     }
 
     pub fn ld_at_n16_sp(&mut self) {
-        let n16 = self.fetch_word();
-        let sp = self.reg.get16(R16::SP);
-        self.write_word(n16, sp);
+        match self.instruction_m_cycles_remaining {
+            // Fetch
+            5 => (),
+            // Read the lower byte
+            4 => self.word_buf_low = self.fetch_byte(),
+            // Read the upper byte
+            3 => self.word_buf_high = self.fetch_byte(),
+            // Write the low byte of SP to [n16]
+            2 => {
+                let addr = self.get_word_buf();
+                let low_byte = self.reg.get16(R16::SP) as u8;
+                self.write_byte(addr, low_byte);
+            }
+            // Write the high byte of SP to [n16 + 1]
+            1 => {
+                let addr = self.get_word_buf().wrapping_add(1);
+                let high_byte = (self.reg.get16(R16::SP) >> 8) as u8;
+                self.write_byte(addr, high_byte);
+            }
+            _ => unreachable!(),
+        }
     }
 
     // This is a weird one. I'm having it use a function from alu.rs ADD 16-bit
@@ -352,7 +369,15 @@ impl Cpu {
     }
 
     pub fn ld_sp_hl(&mut self) {
-        let hl = self.reg.get16(R16::HL);
-        self.reg.set16(R16::SP, hl);
+        match self.instruction_m_cycles_remaining {
+            // Fetch
+            2 => (),
+            // Internal
+            1 => {
+                let hl = self.reg.get16(R16::HL);
+                self.reg.set16(R16::SP, hl);
+            }
+            _ => unreachable!(),
+        }
     }
 }
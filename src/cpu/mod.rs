@@ -2,6 +2,8 @@ use super::*;
 
 mod alu;
 mod bits;
+pub mod call_stack;
+pub mod code_data_log;
 mod instructions;
 mod interrupts;
 mod jumps;
@@ -9,18 +11,22 @@ mod loads;
 pub mod registers;
 
 use crate::constants::M_CYCLE_DURATION;
-use crate::mmu::Mmu;
+use crate::logging::{CPU, INTERRUPTS};
+use crate::mmu::{Bus, Mmu};
 use crate::mmu::memmap::{
-    IE_ADDR, IF_ADDR, JOYPAD_INTERRUPT_BIT, JOYPAD_INTERRUPT_HANDLER_ADDR, LY_ADDR,
+    DIV_ADDR, IE_ADDR, IF_ADDR, P1_ADDR, JOYPAD_INTERRUPT_BIT, JOYPAD_INTERRUPT_HANDLER_ADDR, LY_ADDR,
     SERIAL_INTERRUPT_BIT, SERIAL_INTERRUPT_HANDLER_ADDR, STAT_INTERRUPT_BIT,
     STAT_INTERRUPT_HANDLER_ADDR, TIMER_INTERRUPT_BIT, TIMER_INTERRUPT_HANDLER_ADDR,
     VBLANK_INTERRUPT_BIT, VBLANK_INTERRUPT_HANDLER_ADDR,
 };
 
 use crate::util::{get_bit, set_bit};
+use log::{debug, trace};
 
 use alu::{AluBinary, AluUnary};
 use bits::{BitflagOp, BitshiftOp};
+use call_stack::CallFrame;
+use code_data_log::{Access, CodeDataLog};
 use interrupts::Interrupt;
 use registers::{Flag, R8, R16, Registers};
 
@@ -31,14 +37,17 @@ pub const UNPREFIXED_INSTRUCTION_T_CYCLE_TABLE: &[u8; 256] =
 pub const PREFIXED_INSTRUCTION_T_CYCLE_TABLE: &[u8; 256] =
     include_bytes!("../../data/prefixed_instruction_t_cycle_table.dat");
 
-pub struct Cpu {
+/// The CPU is generic over its memory bus so that it can be tested against flat memory.
+/// When emulating a real Gameboy, the bus is always the MMU.
+pub struct Cpu<B: Bus = Mmu> {
     pub reg: Registers,
-    pub mmu: Rc<RefCell<Mmu>>,
+    pub mmu: Rc<RefCell<B>>,
 
     ime: bool,
     ime_pending: bool,
     halted: bool,
     halt_bug_active: bool,
+    stopped: bool,
 
     handling_interrupt: bool,
     current_interrupt_bit: u8,
@@ -47,6 +56,7 @@ pub struct Cpu {
 
     prev_instruction: u8,
     current_instruction: u8,
+    current_instruction_addr: u16,
     current_instruction_is_prefixed: bool,
     pub instruction_t_cycles_remaining: u8,
     instruction_m_cycles_remaining: u8,
@@ -54,10 +64,13 @@ pub struct Cpu {
     byte_buf: u8,
     word_buf_low: u8,
     word_buf_high: u8,
+
+    call_stack: Vec<CallFrame>,
+    code_data_log: Option<RefCell<CodeDataLog>>,
 }
 
-impl Cpu {
-    pub fn new(mmu: Rc<RefCell<Mmu>>) -> Cpu {
+impl<B: Bus> Cpu<B> {
+    pub fn new(mmu: Rc<RefCell<B>>) -> Cpu<B> {
         Cpu {
             reg: Registers::new(),
             mmu,
@@ -66,6 +79,7 @@ impl Cpu {
             ime_pending: false,
             halted: false,
             halt_bug_active: false,
+            stopped: false,
 
             handling_interrupt: false,
             current_interrupt_bit: 0,
@@ -74,6 +88,7 @@ impl Cpu {
 
             prev_instruction: 0,
             current_instruction: 0,
+            current_instruction_addr: 0x0000,
             current_instruction_is_prefixed: false,
             instruction_t_cycles_remaining: 0,
             instruction_m_cycles_remaining: 0,
@@ -81,9 +96,22 @@ impl Cpu {
             byte_buf: 0x00,
             word_buf_high: 0x00,
             word_buf_low: 0x00,
+
+            call_stack: Vec::new(),
+            code_data_log: None,
         }
     }
 
+    /// True when the next tick fetches a new instruction,
+    /// meaning that PC points at the instruction that is about to run.
+    pub fn at_instruction_boundary(&self) -> bool {
+        self.instruction_t_cycles_remaining <= 1
+            && !self.current_instruction_is_prefixed
+            && !self.handling_interrupt
+            && !self.halted
+            && !self.stopped
+    }
+
     pub fn tick(&mut self) {
         // Update timings
         self.instruction_t_cycles_remaining = self.instruction_t_cycles_remaining.saturating_sub(1);
@@ -96,7 +124,9 @@ impl Cpu {
     }
 
     fn step(&mut self) {
-      
+        if self.update_stop_status() {
+            return;
+        }
 
         self.update_interrupt_status();
         if self.ime_pending {
@@ -120,12 +150,13 @@ impl Cpu {
     // This is basically fetch_byte, but with the halt bug implemented.
     pub fn fetch_instruction(&mut self) -> u8 {
         let pc = self.reg.get16(R16::PC);
-        let byte = self.read_byte(pc);
+        let byte = self.mmu.borrow().read_byte(pc);
+        self.log_access(pc, Access::Opcode);
 
         let next_addr = if !self.halt_bug_active {
             pc.wrapping_add(1)
         } else {
-            println!("Halt bug!");
+            debug!(target: CPU, "Halt bug at {:04x}", pc);
             self.halt_bug_active = false;
             pc
         };
@@ -136,12 +167,13 @@ impl Cpu {
 
     fn fetch_byte(&mut self) -> u8 {
         let pc = self.reg.get16(R16::PC);
-        let byte = self.read_byte(pc);
+        let byte = self.mmu.borrow().read_byte(pc);
+        self.log_access(pc, Access::Operand);
 
         let next_addr = if !self.halt_bug_active {
-            pc + 1
+            pc.wrapping_add(1)
         } else {
-            println!("Halt bug!");
+            debug!(target: CPU, "Halt bug at {:04x}", pc);
             self.halt_bug_active = false;
             pc
         };
@@ -154,15 +186,6 @@ impl Cpu {
         (self.word_buf_low as u16) | ((self.word_buf_high as u16) << 8)
     }
 
-    fn fetch_word(&mut self) -> u16 {
-        let pc = self.reg.get16(R16::PC);
-        let word = self.mmu.borrow_mut().read_word(pc);
-
-        let next_addr = pc + 2;
-        self.reg.set16(R16::PC, next_addr);
-
-        word
-    }
     // Tons of instructions read or write at hl, so I extracted out the logic here
     fn read_at_hl(&self) -> u8 {
         let hl = self.reg.get16(R16::HL);
@@ -176,15 +199,23 @@ impl Cpu {
 
     // Wrapper functions arround MMU reads/writes to make them more clear and ergonomic
     fn read_byte(&self, addr: u16) -> u8 {
-        self.mmu.borrow().read_byte(addr)
+        self.log_access(addr, Access::Read);
+        let mmu = self.mmu.borrow();
+        let byte = mmu.read_byte(addr);
+        mmu.watch_access(addr, false, byte);
+        byte
     }
 
     fn write_byte(&self, addr: u16, byte: u8) {
-        self.mmu.borrow_mut().write_byte(addr, byte);
+        self.log_access(addr, Access::Write);
+        let mut mmu = self.mmu.borrow_mut();
+        mmu.watch_access(addr, true, byte);
+        mmu.write_byte(addr, byte);
     }
 
-    fn write_word(&self, addr: u16, word: u16) {
-        self.mmu.borrow_mut().write_word(addr, word);
+    // The CPU checks the interrupt lines directly. This isn't a memory access.
+    fn read_byte_override(&self, addr: u16) -> u8 {
+        self.mmu.borrow().read_byte_override(addr)
     }
 }
 
@@ -214,7 +245,4 @@ pub mod debug {
 }
 
 #[cfg(test)]
-mod tests {
-    #[test]
-    fn test() {}
-}
+mod tests;
@@ -31,6 +31,7 @@ pub enum R16 {
 }
 
 // F-register flags
+#[derive(Clone, Copy, Debug)]
 pub enum Flag {
     Z,
     N,
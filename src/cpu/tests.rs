@@ -0,0 +1,275 @@
+//! Per-opcode conformance tests against the SM83 [SingleStepTests](https://github.com/SingleStepTests/sm83).
+//! Each JSON file holds the tests for one opcode. A test has the initial and final CPU/RAM state,
+//! as well as the bus activity for every m-cycle of the instruction.
+//!
+//! The test files aren't checked into the repo. Put the `v1` directory from the repository above
+//! at `./test-roms/sm83/v1/` and run them with `cargo test -- --ignored`.
+//! A single opcode can be selected with the `SM83_OPCODE` environment variable (e.g. `SM83_OPCODE="cb 46"`).
+
+use super::*;
+
+use serde_json::Value;
+use std::{cell::RefCell, path::Path};
+
+const SINGLE_STEP_TESTS_PATH: &str = "./test-roms/sm83/v1/";
+const OPCODE_FILTER_ENV_VAR: &str = "SM83_OPCODE";
+/// Stop reporting individual failures after this many, per opcode
+const MAX_REPORTED_FAILURES: usize = 3;
+
+#[derive(PartialEq, Debug)]
+pub(crate) enum BusAccess {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+/// Flat 64 KiB memory with none of the MMU's special cases.
+/// Every read and write is recorded, so that accesses can be checked cycle by cycle.
+/// The accesses the CPU reports for watching are recorded separately.
+pub(crate) struct TestBus {
+    pub(crate) memory: Vec<u8>,
+    accesses: RefCell<Vec<BusAccess>>,
+    watched_accesses: RefCell<Vec<BusAccess>>,
+}
+
+impl TestBus {
+    fn new() -> Self {
+        TestBus {
+            memory: vec![0; 0x10000],
+            accesses: RefCell::new(Vec::new()),
+            watched_accesses: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// A CPU on a fresh bus, with `program` loaded at `addr` and PC pointing at it.
+    pub(crate) fn cpu_with_program(addr: u16, program: &[u8]) -> (Rc<RefCell<Self>>, Cpu<Self>) {
+        let bus = Rc::new(RefCell::new(TestBus::new()));
+        let start = addr as usize;
+        bus.borrow_mut().memory[start..start + program.len()].copy_from_slice(program);
+
+        let mut cpu = Cpu::new(Rc::clone(&bus));
+        cpu.reg.set16(R16::PC, addr);
+        (bus, cpu)
+    }
+
+    fn take_accesses(&self) -> Vec<BusAccess> {
+        std::mem::take(&mut *self.accesses.borrow_mut())
+    }
+
+    pub(crate) fn take_watched_accesses(&self) -> Vec<BusAccess> {
+        std::mem::take(&mut *self.watched_accesses.borrow_mut())
+    }
+}
+
+impl Bus for TestBus {
+    fn read_byte(&self, addr: u16) -> u8 {
+        let byte = self.memory[addr as usize];
+        self.accesses.borrow_mut().push(BusAccess::Read(addr, byte));
+        byte
+    }
+
+    fn write_byte(&mut self, addr: u16, byte: u8) {
+        self.memory[addr as usize] = byte;
+        self.accesses.borrow_mut().push(BusAccess::Write(addr, byte));
+    }
+
+    fn read_byte_override(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn watch_access(&self, addr: u16, write: bool, value: u8) {
+        let access = match write {
+            true => BusAccess::Write(addr, value),
+            false => BusAccess::Read(addr, value),
+        };
+        self.watched_accesses.borrow_mut().push(access);
+    }
+}
+
+fn get_u16(state: &Value, key: &str) -> u16 {
+    state[key]
+        .as_u64()
+        .unwrap_or_else(|| panic!("test state is missing \"{}\"", key)) as u16
+}
+
+fn get_ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .expect("test state is missing \"ram\"")
+        .iter()
+        .map(|entry| (entry[0].as_u64().unwrap() as u16, entry[1].as_u64().unwrap() as u8))
+        .collect()
+}
+
+const R8_KEYS: [(R8, &str); 8] = [
+    (R8::A, "a"),
+    (R8::B, "b"),
+    (R8::C, "c"),
+    (R8::D, "d"),
+    (R8::E, "e"),
+    (R8::F, "f"),
+    (R8::H, "h"),
+    (R8::L, "l"),
+];
+
+fn create_test_cpu(initial: &Value) -> Cpu<TestBus> {
+    let bus = Rc::new(RefCell::new(TestBus::new()));
+    let mut cpu = Cpu::new(Rc::clone(&bus));
+
+    for (r8, key) in R8_KEYS {
+        cpu.reg.set(r8, get_u16(initial, key) as u8);
+    }
+    cpu.reg.set16(R16::PC, get_u16(initial, "pc"));
+    cpu.reg.set16(R16::SP, get_u16(initial, "sp"));
+    cpu.ime = get_u16(initial, "ime") != 0;
+
+    let mut bus = bus.borrow_mut();
+    for (addr, byte) in get_ram(initial) {
+        bus.memory[addr as usize] = byte;
+    }
+    if let Some(ie) = initial["ie"].as_u64() {
+        bus.memory[IE_ADDR as usize] = ie as u8;
+    }
+
+    cpu
+}
+
+fn get_expected_access(cycle: &Value) -> Option<BusAccess> {
+    // Idle cycles are either null, or have no read/write flags
+    let kind = cycle[2].as_str()?;
+    let addr = cycle[0].as_u64()? as u16;
+    let byte = cycle[1].as_u64().unwrap_or(0) as u8;
+
+    if kind.starts_with('r') {
+        Some(BusAccess::Read(addr, byte))
+    } else if kind.contains('w') {
+        Some(BusAccess::Write(addr, byte))
+    } else {
+        None
+    }
+}
+
+/// Runs a single test, returning a description of the first mismatch found.
+fn run_test(test: &Value) -> Result<(), String> {
+    let mut cpu = create_test_cpu(&test["initial"]);
+    let cycles = test["cycles"].as_array().ok_or("test is missing \"cycles\"")?;
+
+    for (m_cycle, cycle) in cycles.iter().enumerate() {
+        for _t_cycle in 0..M_CYCLE_DURATION {
+            cpu.tick();
+        }
+
+        let accesses = cpu.mmu.borrow().take_accesses();
+        let expected: Vec<BusAccess> = get_expected_access(cycle).into_iter().collect();
+        if accesses != expected {
+            return Err(format!(
+                "m-cycle {}: expected bus activity {:x?}, got {:x?}",
+                m_cycle, expected, accesses
+            ));
+        }
+    }
+
+    let expected = &test["final"];
+    for (r8, key) in R8_KEYS {
+        let value = cpu.reg.get(r8) as u16;
+        let expected_value = get_u16(expected, key);
+        if value != expected_value {
+            return Err(format!("{}: expected {:02x}, got {:02x}", key, expected_value, value));
+        }
+    }
+    for (r16, key) in [(R16::PC, "pc"), (R16::SP, "sp")] {
+        let value = cpu.reg.get16(r16);
+        let expected_value = get_u16(expected, key);
+        if value != expected_value {
+            return Err(format!("{}: expected {:04x}, got {:04x}", key, expected_value, value));
+        }
+    }
+
+    // EI's effect is delayed by one instruction, but the tests count it as already enabled.
+    let ime = cpu.ime || cpu.ime_pending;
+    let expected_ime = get_u16(expected, "ime") != 0;
+    if ime != expected_ime {
+        return Err(format!("ime: expected {}, got {}", expected_ime, ime));
+    }
+
+    let bus = cpu.mmu.borrow();
+    for (addr, expected_byte) in get_ram(expected) {
+        let byte = bus.memory[addr as usize];
+        if byte != expected_byte {
+            return Err(format!(
+                "[{:04x}]: expected {:02x}, got {:02x}",
+                addr, expected_byte, byte
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the number of failed tests in the file.
+fn run_test_file(path: &Path) -> usize {
+    let json = std::fs::read_to_string(path).expect("failed to read test file");
+    let tests: Vec<Value> = serde_json::from_str(&json).expect("failed to parse test file");
+
+    let mut failures = 0;
+    for test in &tests {
+        if let Err(message) = run_test(test) {
+            if failures < MAX_REPORTED_FAILURES {
+                eprintln!("{}: {}", test["name"].as_str().unwrap_or("?"), message);
+            }
+            failures += 1;
+        }
+    }
+
+    if failures != 0 {
+        eprintln!("{:?}: {} of {} tests failed\n", path, failures, tests.len());
+    }
+    failures
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests files in ./test-roms/sm83/v1/"]
+fn test_single_step_tests() {
+    let Ok(entries) = std::fs::read_dir(SINGLE_STEP_TESTS_PATH) else {
+        panic!("No SingleStepTests files found at \"{}\"", SINGLE_STEP_TESTS_PATH);
+    };
+
+    let filter = std::env::var(OPCODE_FILTER_ENV_VAR).ok();
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .filter(|path| match &filter {
+            Some(opcode) => path.file_stem().is_some_and(|stem| stem == opcode.as_str()),
+            None => true,
+        })
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "No SingleStepTests files matched");
+
+    let failed_files: Vec<_> = paths.iter().filter(|path| run_test_file(path) != 0).collect();
+    assert!(
+        failed_files.is_empty(),
+        "{} of {} opcodes failed: {:?}",
+        failed_files.len(),
+        paths.len(),
+        failed_files
+    );
+}
+
+#[test]
+fn test_test_bus_records_accesses() {
+    // LD [HL], A
+    let (bus, mut cpu) = TestBus::cpu_with_program(0xC000, &[0x77]);
+    cpu.reg.set16(R16::HL, 0xD000);
+    cpu.reg.set(R8::A, 0x42);
+    cpu.ime = false;
+
+    for _t_cycle in 0..M_CYCLE_DURATION {
+        cpu.tick();
+    }
+    assert_eq!(bus.borrow().take_accesses(), vec![BusAccess::Read(0xC000, 0x77)]);
+
+    for _t_cycle in 0..M_CYCLE_DURATION {
+        cpu.tick();
+    }
+    assert_eq!(bus.borrow().take_accesses(), vec![BusAccess::Write(0xD000, 0x42)]);
+}
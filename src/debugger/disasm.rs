@@ -0,0 +1,199 @@
+//! A disassembler for the debugger, using RGBDS syntax.
+//! Opcodes are decoded from their bit fields rather than a 512 entry table. The layout is
+//! described [here](https://gbdev.io/pandocs/CPU_Instruction_Set.html).
+
+use super::symbols::Symbols;
+use crate::mmu::Mmu;
+
+const R8_NAMES: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const R16_NAMES: [&str; 4] = ["bc", "de", "hl", "sp"];
+const R16_STACK_NAMES: [&str; 4] = ["bc", "de", "hl", "af"];
+const R16_MEM_NAMES: [&str; 4] = ["[bc]", "[de]", "[hl+]", "[hl-]"];
+const CONDITION_NAMES: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU_NAMES: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const SHIFT_NAMES: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl Instruction {
+    pub fn len(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+}
+
+pub fn disassemble(mmu: &Mmu, symbols: &Symbols, addr: u16) -> Instruction {
+    let read = |offset: u16| mmu.read_byte_override(addr.wrapping_add(offset));
+    let opcode = read(0);
+    let n8 = read(1);
+    let n16 = (read(1) as u16) | ((read(2) as u16) << 8);
+
+    // Instruction operands show labels where the symbol file has them
+    let label = |target: u16| match symbols.get_label(target) {
+        Some(name) => name.to_string(),
+        None => format!("${:04x}", target),
+    };
+    let relative_target = addr.wrapping_add(2).wrapping_add(n8 as i8 as u16);
+
+    let y = ((opcode >> 3) & 0b111) as usize;
+    let z = (opcode & 0b111) as usize;
+    let p = y >> 1;
+
+    let (text, len) = match opcode >> 6 {
+        0 => match opcode {
+            0x00 => ("nop".to_string(), 1),
+            0x08 => (format!("ld [{}], sp", label(n16)), 3),
+            0x10 => ("stop".to_string(), 2),
+            0x18 => (format!("jr {}", label(relative_target)), 2),
+            0x20 | 0x28 | 0x30 | 0x38 => (
+                format!("jr {}, {}", CONDITION_NAMES[y - 4], label(relative_target)),
+                2,
+            ),
+            0x07 => ("rlca".to_string(), 1),
+            0x0F => ("rrca".to_string(), 1),
+            0x17 => ("rla".to_string(), 1),
+            0x1F => ("rra".to_string(), 1),
+            0x27 => ("daa".to_string(), 1),
+            0x2F => ("cpl".to_string(), 1),
+            0x37 => ("scf".to_string(), 1),
+            0x3F => ("ccf".to_string(), 1),
+            _ => match (z, y & 1) {
+                (1, 0) => (format!("ld {}, {}", R16_NAMES[p], label(n16)), 3),
+                (1, _) => (format!("add hl, {}", R16_NAMES[p]), 1),
+                (2, 0) => (format!("ld {}, a", R16_MEM_NAMES[p]), 1),
+                (2, _) => (format!("ld a, {}", R16_MEM_NAMES[p]), 1),
+                (3, 0) => (format!("inc {}", R16_NAMES[p]), 1),
+                (3, _) => (format!("dec {}", R16_NAMES[p]), 1),
+                (4, _) => (format!("inc {}", R8_NAMES[y]), 1),
+                (5, _) => (format!("dec {}", R8_NAMES[y]), 1),
+                (6, _) => (format!("ld {}, ${:02x}", R8_NAMES[y], n8), 2),
+                _ => unreachable!(),
+            },
+        },
+        1 => match opcode {
+            0x76 => ("halt".to_string(), 1),
+            _ => (format!("ld {}, {}", R8_NAMES[y], R8_NAMES[z]), 1),
+        },
+        2 => (format!("{} a, {}", ALU_NAMES[y], R8_NAMES[z]), 1),
+        _ => match opcode {
+            0xC9 => ("ret".to_string(), 1),
+            0xD9 => ("reti".to_string(), 1),
+            0xC3 => (format!("jp {}", label(n16)), 3),
+            0xE9 => ("jp hl".to_string(), 1),
+            0xCD => (format!("call {}", label(n16)), 3),
+            0xCB => (disassemble_prefixed(n8), 2),
+            0xE0 => (format!("ldh [{}], a", label(0xFF00 | n8 as u16)), 2),
+            0xF0 => (format!("ldh a, [{}]", label(0xFF00 | n8 as u16)), 2),
+            0xE2 => ("ldh [c], a".to_string(), 1),
+            0xF2 => ("ldh a, [c]".to_string(), 1),
+            0xEA => (format!("ld [{}], a", label(n16)), 3),
+            0xFA => (format!("ld a, [{}]", label(n16)), 3),
+            0xE8 => (format!("add sp, {}", n8 as i8), 2),
+            0xF8 => (format!("ld hl, sp{:+}", n8 as i8), 2),
+            0xF9 => ("ld sp, hl".to_string(), 1),
+            0xF3 => ("di".to_string(), 1),
+            0xFB => ("ei".to_string(), 1),
+            0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+                (format!("db ${:02x}", opcode), 1)
+            }
+            _ => match z {
+                0 => (format!("ret {}", CONDITION_NAMES[y]), 1),
+                1 => (format!("pop {}", R16_STACK_NAMES[p]), 1),
+                2 => (format!("jp {}, {}", CONDITION_NAMES[y], label(n16)), 3),
+                4 => (format!("call {}, {}", CONDITION_NAMES[y], label(n16)), 3),
+                5 => (format!("push {}", R16_STACK_NAMES[p]), 1),
+                6 => (format!("{} a, ${:02x}", ALU_NAMES[y], n8), 2),
+                7 => (format!("rst {}", label(opcode as u16 & 0x38)), 1),
+                _ => unreachable!(),
+            },
+        },
+    };
+
+    Instruction {
+        addr,
+        bytes: (0..len).map(read).collect(),
+        text,
+    }
+}
+
+fn disassemble_prefixed(opcode: u8) -> String {
+    let bit = (opcode >> 3) & 0b111;
+    let register = R8_NAMES[(opcode & 0b111) as usize];
+
+    match opcode >> 6 {
+        0 => format!("{} {}", SHIFT_NAMES[bit as usize], register),
+        1 => format!("bit {}, {}", bit, register),
+        2 => format!("res {}, {}", bit, register),
+        _ => format!("set {}, {}", bit, register),
+    }
+}
+
+/// Formats an instruction as a line of a disassembly listing, e.g.
+/// `0150 <Main>          3e 01     ld a, $01`
+pub fn format_instruction(instruction: &Instruction, symbols: &Symbols) -> String {
+    let bytes: Vec<String> = instruction
+        .bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!(
+        "{:<24}{:<10}{}",
+        symbols.format_addr(instruction.addr),
+        bytes.join(" "),
+        instruction.text
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disassemble_bytes(bytes: &[u8], symbols: &Symbols) -> (String, u16) {
+        let mmu = Mmu::new();
+        let mut mmu = mmu.borrow_mut();
+        for (i, byte) in bytes.iter().enumerate() {
+            mmu.write_byte_override(0xC000 + i as u16, *byte);
+        }
+        let instruction = disassemble(&mmu, symbols, 0xC000);
+        (instruction.text.clone(), instruction.len())
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let symbols = Symbols::new();
+        let cases: [(&[u8], &str, u16); 14] = [
+            (&[0x00], "nop", 1),
+            (&[0x01, 0x34, 0x12], "ld bc, $1234", 3),
+            (&[0x22], "ld [hl+], a", 1),
+            (&[0x36, 0x42], "ld [hl], $42", 2),
+            (&[0x18, 0xFE], "jr $c000", 2),
+            (&[0x38, 0x02], "jr c, $c004", 2),
+            (&[0x7E], "ld a, [hl]", 1),
+            (&[0xAF], "xor a, a", 1),
+            (&[0xC4, 0x00, 0x40], "call nz, $4000", 3),
+            (&[0xF1], "pop af", 1),
+            (&[0xFE, 0x90], "cp a, $90", 2),
+            (&[0xF8, 0xFE], "ld hl, sp-2", 2),
+            (&[0xCB, 0x7C], "bit 7, h", 2),
+            (&[0xCB, 0x37], "swap a", 2),
+        ];
+
+        for (bytes, text, len) in cases {
+            assert_eq!(disassemble_bytes(bytes, &symbols), (text.to_string(), len));
+        }
+    }
+
+    #[test]
+    fn test_disassemble_with_labels() {
+        let symbols = Symbols::parse("00:0150 Main\n00:ff44 rLY\n00:0038 Rst38");
+        assert_eq!(
+            disassemble_bytes(&[0xC3, 0x50, 0x01], &symbols).0,
+            "jp Main"
+        );
+        assert_eq!(disassemble_bytes(&[0xF0, 0x44], &symbols).0, "ldh a, [rLY]");
+        assert_eq!(disassemble_bytes(&[0xFF], &symbols).0, "rst Rst38");
+    }
+}
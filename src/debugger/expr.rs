@@ -0,0 +1,344 @@
+//! Expressions for conditional breakpoints, watchpoints, and the `print` command.
+//!
+//! Operands are numbers (decimal, or hex with a `0x`/`$` prefix), registers (`a`, `hl`, ...),
+//! flags (`zf`, `nf`, `hf`, `cf`), `ly`, symbol labels, and memory reads (`[hl]`, `[ff44]`).
+//! An address on its own inside brackets is hex, like everywhere else in the debugger.
+//! Operators follow Rust's precedence, and `and`/`or`/`not` work as aliases for `&&`/`||`/`!`.
+//!
+//! e.g. `a == 3 and [ff44] > 140`
+
+use super::{Register, parse_address, parse_register, symbols::Symbols};
+use crate::cpu::{Cpu, registers::Flag};
+use crate::mmu::memmap::LY_ADDR;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+    BitNot,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    BitAnd,
+    BitXor,
+    BitOr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+/// Binary operators grouped by precedence, loosest binding first.
+const PRECEDENCE: [&[(&str, BinaryOp)]; 9] = [
+    &[("||", BinaryOp::Or), ("or", BinaryOp::Or)],
+    &[("&&", BinaryOp::And), ("and", BinaryOp::And)],
+    &[
+        ("==", BinaryOp::Eq),
+        ("!=", BinaryOp::Ne),
+        ("<=", BinaryOp::Le),
+        (">=", BinaryOp::Ge),
+        ("<", BinaryOp::Lt),
+        (">", BinaryOp::Gt),
+    ],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+    ],
+];
+
+#[derive(Clone)]
+pub enum Expr {
+    Number(i64),
+    Register(Register),
+    Flag(Flag),
+    Ly,
+    Memory(Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn eval(&self, cpu: &Cpu) -> i64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Register(Register::R8(r8)) => cpu.reg.get(*r8) as i64,
+            Expr::Register(Register::R16(r16)) => cpu.reg.get16(*r16) as i64,
+            Expr::Flag(flag) => cpu.reg.get_flag(*flag) as i64,
+            Expr::Ly => cpu.mmu.borrow().read_byte_override(LY_ADDR) as i64,
+            Expr::Memory(addr) => {
+                let addr = addr.eval(cpu) as u16;
+                cpu.mmu.borrow().read_byte_override(addr) as i64
+            }
+            Expr::Unary(op, operand) => {
+                let value = operand.eval(cpu);
+                match op {
+                    UnaryOp::Negate => value.wrapping_neg(),
+                    UnaryOp::Not => (value == 0) as i64,
+                    UnaryOp::BitNot => !value,
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(cpu);
+                // Logical operators short-circuit
+                match op {
+                    BinaryOp::And if lhs == 0 => return 0,
+                    BinaryOp::Or if lhs != 0 => return 1,
+                    _ => (),
+                }
+                let rhs = rhs.eval(cpu);
+                match op {
+                    BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                    BinaryOp::Div => lhs.checked_div(rhs).unwrap_or(0),
+                    BinaryOp::Rem => lhs.checked_rem(rhs).unwrap_or(0),
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                    BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+                    BinaryOp::Shr => lhs.wrapping_shr(rhs as u32),
+                    BinaryOp::BitAnd => lhs & rhs,
+                    BinaryOp::BitXor => lhs ^ rhs,
+                    BinaryOp::BitOr => lhs | rhs,
+                    BinaryOp::Eq => (lhs == rhs) as i64,
+                    BinaryOp::Ne => (lhs != rhs) as i64,
+                    BinaryOp::Lt => (lhs < rhs) as i64,
+                    BinaryOp::Le => (lhs <= rhs) as i64,
+                    BinaryOp::Gt => (lhs > rhs) as i64,
+                    BinaryOp::Ge => (lhs >= rhs) as i64,
+                    BinaryOp::And | BinaryOp::Or => (rhs != 0) as i64,
+                }
+            }
+        }
+    }
+
+    pub fn is_true(&self, cpu: &Cpu) -> bool {
+        self.eval(cpu) != 0
+    }
+}
+
+pub fn parse_expr(text: &str, symbols: &Symbols) -> Result<Expr, String> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        symbols,
+    };
+
+    let expr = parser.parse_binary(0)?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("Unexpected \"{}\"", token)),
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    const SYMBOLS: [&str; 23] = [
+        "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "|", "^", "&", "+", "-", "*",
+        "/", "%", "!", "~", "(", ")", "[",
+    ];
+
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+
+    while !rest.is_empty() {
+        let word_len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'))
+            .unwrap_or(rest.len());
+
+        let len = if word_len > 0 {
+            word_len
+        } else if rest.starts_with(']') {
+            1
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            symbol.len()
+        } else {
+            return Err(format!("Unexpected \"{}\"", rest.chars().next().unwrap()));
+        };
+
+        tokens.push(rest[..len].to_string());
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<String>,
+    position: usize,
+    symbols: &'a Symbols,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("Expected \"{}\", found \"{}\"", expected, token)),
+            None => Err(format!("Expected \"{}\"", expected)),
+        }
+    }
+
+    /// Precedence climbing, one level of the table at a time
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, String> {
+        let mut lhs = self.parse_binary_operand(level)?;
+        while let Some(&(_, op)) = self.peek().and_then(|token| {
+            PRECEDENCE[level]
+                .iter()
+                .find(|(name, _)| token.eq_ignore_ascii_case(name))
+        }) {
+            self.next();
+            let rhs = self.parse_binary_operand(level)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_binary_operand(&mut self, level: usize) -> Result<Expr, String> {
+        if level + 1 < PRECEDENCE.len() {
+            self.parse_binary(level + 1)
+        } else {
+            self.parse_unary()
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        let op = match self.peek().map(str::to_lowercase).as_deref() {
+            Some("-") => UnaryOp::Negate,
+            Some("!") | Some("not") => UnaryOp::Not,
+            Some("~") => UnaryOp::BitNot,
+            _ => return self.parse_operand(),
+        };
+        self.next();
+        Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+    }
+
+    fn parse_operand(&mut self) -> Result<Expr, String> {
+        let token = self.next().ok_or("Unexpected end of expression")?;
+
+        match token.as_str() {
+            "(" => {
+                let expr = self.parse_binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            "[" => {
+                // A lone address is hex, like the rest of the debugger's address arguments
+                let is_lone_word =
+                    self.tokens.get(self.position + 1).map(String::as_str) == Some("]");
+                let addr = match self.peek() {
+                    Some(word) if is_lone_word && parse_register(word).is_none() => {
+                        let addr = parse_address(word, self.symbols)
+                            .ok_or_else(|| format!("Invalid address \"{}\"", word))?;
+                        self.next();
+                        Expr::Number(addr as i64)
+                    }
+                    _ => self.parse_binary(0)?,
+                };
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(addr)))
+            }
+            word => self.parse_word(word),
+        }
+    }
+
+    fn parse_word(&self, word: &str) -> Result<Expr, String> {
+        if let Some(register) = parse_register(word) {
+            return Ok(Expr::Register(register));
+        }
+
+        let lowercase = word.to_lowercase();
+        let flag = match lowercase.as_str() {
+            "zf" => Some(Flag::Z),
+            "nf" => Some(Flag::N),
+            "hf" => Some(Flag::H),
+            "cf" => Some(Flag::C),
+            _ => None,
+        };
+        if let Some(flag) = flag {
+            return Ok(Expr::Flag(flag));
+        }
+        if lowercase == "ly" {
+            return Ok(Expr::Ly);
+        }
+        if let Some(addr) = self.symbols.get_addr(word) {
+            return Ok(Expr::Number(addr as i64));
+        }
+
+        let number = if let Some(hex) = lowercase.strip_prefix("0x").or(lowercase.strip_prefix('$'))
+        {
+            i64::from_str_radix(hex, 16).ok()
+        } else {
+            lowercase.parse().ok()
+        };
+        number
+            .map(Expr::Number)
+            .ok_or_else(|| format!("Unknown value \"{}\"", word))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::registers::{R8, R16};
+    use crate::create_gameboy_components;
+
+    fn eval(text: &str, cpu: &Cpu) -> i64 {
+        let symbols = Symbols::parse("00:c000 wCounter");
+        parse_expr(text, &symbols).unwrap().eval(cpu)
+    }
+
+    #[test]
+    fn test_eval() {
+        let (mmu, mut cpu, _ppu) = create_gameboy_components();
+        cpu.reg.set(R8::A, 3);
+        cpu.reg.set16(R16::HL, 0xC000);
+        cpu.reg.set_flag(Flag::C, true);
+        mmu.borrow_mut().write_byte_override(LY_ADDR, 144);
+        mmu.borrow_mut().write_byte_override(0xC000, 0x42);
+
+        assert_eq!(eval("1 + 2 * 3", &cpu), 7);
+        assert_eq!(eval("(1 + 2) * 3", &cpu), 9);
+        assert_eq!(eval("a == 3 and [ff44] > 140", &cpu), 1);
+        assert_eq!(eval("A == 3 && LY < 140", &cpu), 0);
+        assert_eq!(eval("[hl] == $42 || not cf", &cpu), 1);
+        assert_eq!(eval("[wCounter] + [hl + 1]", &cpu), 0x42);
+        assert_eq!(eval("hl >> 8 & 0xf0", &cpu), 0xC0);
+        assert_eq!(eval("-a + ~0", &cpu), -4);
+        assert_eq!(eval("1 / 0", &cpu), 0);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let symbols = Symbols::new();
+        assert!(parse_expr("a ==", &symbols).is_err());
+        assert!(parse_expr("(a + 1", &symbols).is_err());
+        assert!(parse_expr("a = 3", &symbols).is_err());
+        assert!(parse_expr("[nowhere]", &symbols).is_err());
+        assert!(parse_expr("a b", &symbols).is_err());
+    }
+}
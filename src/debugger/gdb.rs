@@ -0,0 +1,617 @@
+//! A stub for the [GDB remote serial protocol](https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html),
+//! so that the emulator can be debugged from GDB (or an IDE frontend that speaks the protocol)
+//! over a local TCP port.
+//!
+//! Only the subset needed for basic debugging is supported: reading and writing registers and
+//! memory, stepping, continuing, software breakpoints and watchpoints. Unsupported packets get
+//! the empty reply, which tells GDB to fall back to something else.
+//!
+//! GDB has no built-in SM83 architecture, so the register layout is described to it in
+//! `target.xml`. The registers are sent as little-endian 16-bit values, in the order of
+//! `REGISTERS`.
+
+use super::*;
+use std::{
+    collections::BTreeSet,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+pub const DEFAULT_GDB_PORT: u16 = 2345;
+
+const REGISTERS: [R16; 6] = [R16::AF, R16::BC, R16::DE, R16::HL, R16::SP, R16::PC];
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.sm83.core">
+    <reg name="af" bitsize="16" type="int"/>
+    <reg name="bc" bitsize="16" type="int"/>
+    <reg name="de" bitsize="16" type="int"/>
+    <reg name="hl" bitsize="16" type="int"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+// Signal numbers used in stop replies
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// Sent by GDB (outside of a packet) to interrupt the target while it's running
+const INTERRUPT_BYTE: u8 = 0x03;
+
+/// What to do after handling a packet.
+#[derive(Debug, PartialEq)]
+enum Action {
+    Reply(String),
+    Step,
+    Continue,
+    /// Stop serving, after sending the reply (if any)
+    Disconnect(Option<String>),
+}
+
+struct GdbStub {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+pub fn run_gdb_server(path: &str, options: &Options) {
+    let port = options.gdb_port.unwrap_or(DEFAULT_GDB_PORT);
+    println!("\nDebugging rom at: \"{}\"", path);
+
+    let (mmu, mut cpu, mut ppu) = create_gameboy_components();
+
+    if !mmu.borrow_mut().load_rom(path) {
+        println!("Failed to load rom at \"{}\"", path);
+        return;
+    }
+
+    emulate_boot(&mmu, &mut cpu);
+
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(error) => {
+            println!("Failed to listen on port {}: {}", port, error);
+            return;
+        }
+    };
+    println!("Waiting for GDB to connect on port {}", port);
+
+    let mut stream = match listener.accept() {
+        Ok((stream, addr)) => {
+            println!("GDB connected from {}", addr);
+            stream
+        }
+        Err(error) => {
+            println!("Failed to accept a connection: {}", error);
+            return;
+        }
+    };
+    // Packets are small and GDB waits for every reply
+    let _ = stream.set_nodelay(true);
+
+    let mut stub = GdbStub {
+        breakpoints: BTreeSet::new(),
+        watchpoints: Vec::new(),
+    };
+    let mut ui = UserInterface::new(options);
+
+    if let Err(error) = serve(&mut stream, &mut stub, &mut cpu, &mmu, &mut ppu, &mut ui) {
+        println!("GDB connection closed: {}", error);
+    }
+}
+
+fn serve(
+    stream: &mut TcpStream,
+    stub: &mut GdbStub,
+    cpu: &mut Cpu,
+    mmu: &Rc<RefCell<Mmu>>,
+    ppu: &mut Ppu,
+    ui: &mut UserInterface,
+) -> io::Result<()> {
+    while let Some(packet) = read_packet(stream)? {
+        let reply = match handle_packet(&packet, stub, cpu, mmu) {
+            Action::Reply(reply) => reply,
+            Action::Step => resume(true, stream, stub, cpu, mmu, ppu, ui)?,
+            Action::Continue => resume(false, stream, stub, cpu, mmu, ppu, ui)?,
+            Action::Disconnect(reply) => {
+                if let Some(reply) = reply {
+                    send_packet(stream, &reply)?;
+                }
+                return Ok(());
+            }
+        };
+        send_packet(stream, &reply)?;
+
+        // The window was closed while running
+        if !ui.running {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Returns the next packet's data, or None if GDB disconnected.
+/// Packets look like `$data#cc`, where cc is the checksum of the data in hex.
+fn read_packet(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    loop {
+        // Skip acks, and interrupts that arrive after the target already stopped
+        let Some(byte) = read_byte(stream)? else {
+            return Ok(None);
+        };
+        if byte != b'$' {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        loop {
+            match read_byte(stream)? {
+                Some(b'#') => break,
+                Some(byte) => data.push(byte),
+                None => return Ok(None),
+            }
+        }
+
+        let mut checksum_digits = [0; 2];
+        for digit in &mut checksum_digits {
+            match read_byte(stream)? {
+                Some(byte) => *digit = byte,
+                None => return Ok(None),
+            }
+        }
+        let expected = std::str::from_utf8(&checksum_digits)
+            .ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+
+        if expected == Some(checksum(&data)) {
+            stream.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+        // Ask GDB to send it again
+        stream.write_all(b"-")?;
+    }
+}
+
+/// Returns None if GDB disconnected.
+fn read_byte(stream: &mut TcpStream) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match stream.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+fn send_packet(stream: &mut TcpStream, data: &str) -> io::Result<()> {
+    stream.write_all(format_packet(data).as_bytes())
+}
+
+fn format_packet(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum(data.as_bytes()))
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn handle_packet(
+    packet: &str,
+    stub: &mut GdbStub,
+    cpu: &mut Cpu,
+    mmu: &Rc<RefCell<Mmu>>,
+) -> Action {
+    let Some(command) = packet.chars().next() else {
+        return Action::Reply(String::new());
+    };
+    let args = &packet[command.len_utf8()..];
+
+    let reply = match command {
+        '?' => format!("S{:02x}", SIGTRAP),
+        'g' => read_registers(cpu),
+        'G' => ok_or_error(write_registers(cpu, args)),
+        'p' => match parse_hex_u16(args).and_then(|n| REGISTERS.get(n as usize)) {
+            Some(register) => encode_hex(&cpu.reg.get16(*register).to_le_bytes()),
+            None => error_reply(),
+        },
+        'P' => ok_or_error(write_register(cpu, args)),
+        'm' => match parse_memory_args(args) {
+            Some((addr, len)) => read_memory(&mmu.borrow(), addr, len),
+            None => error_reply(),
+        },
+        'M' => ok_or_error(write_memory(&mut mmu.borrow_mut(), args)),
+        // An optional address to resume from can follow both of these
+        's' | 'c' => {
+            if let Some(addr) = parse_hex_u16(args) {
+                cpu.reg.set16(R16::PC, addr);
+            }
+            return if command == 's' {
+                Action::Step
+            } else {
+                Action::Continue
+            };
+        }
+        'Z' => ok_or_empty(set_breakpoint(stub, mmu, args, true)),
+        'z' => ok_or_empty(set_breakpoint(stub, mmu, args, false)),
+        'H' => "OK".to_string(),
+        'q' => handle_query(args),
+        'D' => return Action::Disconnect(Some("OK".to_string())),
+        'k' => return Action::Disconnect(None),
+        _ => String::new(),
+    };
+
+    Action::Reply(reply)
+}
+
+fn handle_query(query: &str) -> String {
+    if query.starts_with("Supported") {
+        return "PacketSize=1000;qXfer:features:read+".to_string();
+    }
+    if let Some(args) = query.strip_prefix("Xfer:features:read:target.xml:") {
+        return match parse_memory_args(args) {
+            Some((offset, len)) => read_target_xml(offset as usize, len as usize),
+            None => error_reply(),
+        };
+    }
+
+    match query {
+        "Attached" => "1".to_string(),
+        // There is only ever one thread
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+/// GDB reads the target description in chunks. The reply starts with `l` for the last chunk.
+fn read_target_xml(offset: usize, len: usize) -> String {
+    let start = offset.min(TARGET_XML.len());
+    let end = offset.saturating_add(len).min(TARGET_XML.len());
+    let prefix = if end == TARGET_XML.len() { 'l' } else { 'm' };
+    format!("{}{}", prefix, &TARGET_XML[start..end])
+}
+
+fn read_registers(cpu: &Cpu) -> String {
+    let bytes: Vec<u8> = REGISTERS
+        .iter()
+        .flat_map(|register| cpu.reg.get16(*register).to_le_bytes())
+        .collect();
+    encode_hex(&bytes)
+}
+
+fn write_registers(cpu: &mut Cpu, args: &str) -> bool {
+    let Some(bytes) = decode_hex(args) else {
+        return false;
+    };
+    if bytes.len() != REGISTERS.len() * 2 {
+        return false;
+    }
+
+    for (register, value) in REGISTERS.iter().zip(bytes.chunks(2)) {
+        cpu.reg
+            .set16(*register, u16::from_le_bytes([value[0], value[1]]));
+    }
+    true
+}
+
+/// `Pn=v`, where v is the value in target byte order.
+fn write_register(cpu: &mut Cpu, args: &str) -> bool {
+    let Some((number, value)) = args.split_once('=') else {
+        return false;
+    };
+    let register = parse_hex_u16(number).and_then(|n| REGISTERS.get(n as usize));
+    let value = decode_hex(value).filter(|bytes| bytes.len() == 2);
+
+    let (Some(register), Some(value)) = (register, value) else {
+        return false;
+    };
+    cpu.reg
+        .set16(*register, u16::from_le_bytes([value[0], value[1]]));
+    true
+}
+
+/// Parses `addr,len`.
+fn parse_memory_args(args: &str) -> Option<(u16, u16)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((parse_hex_u16(addr)?, parse_hex_u16(len)?))
+}
+
+fn read_memory(mmu: &Mmu, addr: u16, len: u16) -> String {
+    let bytes: Vec<u8> = (0..len)
+        .map(|offset| mmu.read_byte_override(addr.wrapping_add(offset)))
+        .collect();
+    encode_hex(&bytes)
+}
+
+/// `addr,len:bytes`
+fn write_memory(mmu: &mut Mmu, args: &str) -> bool {
+    let Some((location, data)) = args.split_once(':') else {
+        return false;
+    };
+    let (Some((addr, len)), Some(bytes)) = (parse_memory_args(location), decode_hex(data)) else {
+        return false;
+    };
+    if bytes.len() != len as usize {
+        return false;
+    }
+
+    for (offset, byte) in bytes.iter().enumerate() {
+        mmu.write_byte_override(addr.wrapping_add(offset as u16), *byte);
+    }
+    true
+}
+
+/// `type,addr,kind`. Types 0 and 1 are breakpoints (every breakpoint is a software breakpoint
+/// here), 2-4 are write, read and access watchpoints, where kind is the length in bytes.
+/// Returns None for unsupported types.
+fn set_breakpoint(
+    stub: &mut GdbStub,
+    mmu: &Rc<RefCell<Mmu>>,
+    args: &str,
+    insert: bool,
+) -> Option<bool> {
+    let mut fields = args.split(',');
+    let kind = fields.next()?;
+    let (Some(addr), Some(len)) = (
+        fields.next().and_then(parse_hex_u16),
+        fields.next().and_then(parse_hex_u16),
+    ) else {
+        return Some(false);
+    };
+
+    let watch_kind = match kind {
+        "0" | "1" => {
+            if insert {
+                stub.breakpoints.insert(addr);
+            } else {
+                stub.breakpoints.remove(&addr);
+            }
+            return Some(true);
+        }
+        "2" => WatchKind::Write,
+        "3" => WatchKind::Read,
+        "4" => WatchKind::Access,
+        _ => return None,
+    };
+
+    let mut mmu = mmu.borrow_mut();
+    for offset in 0..len.max(1) {
+        let watchpoint = Watchpoint {
+            addr: addr.wrapping_add(offset),
+            kind: watch_kind,
+        };
+        if insert {
+            mmu.add_watchpoint(watchpoint);
+            stub.watchpoints.push(watchpoint);
+        } else {
+            mmu.remove_watchpoint(watchpoint);
+            stub.watchpoints.retain(|w| *w != watchpoint);
+        }
+    }
+    Some(true)
+}
+
+/// Runs the whole system until an instruction finishes (when stepping), a breakpoint or
+/// watchpoint is hit, or GDB interrupts it. Returns the stop reply.
+fn resume(
+    step: bool,
+    stream: &mut TcpStream,
+    stub: &GdbStub,
+    cpu: &mut Cpu,
+    mmu: &Rc<RefCell<Mmu>>,
+    ppu: &mut Ppu,
+    ui: &mut UserInterface,
+) -> io::Result<String> {
+    let render_timer_period = Duration::from_secs_f64(1.0 / 60.0);
+    let mut last_render_time = Instant::now();
+
+    // Same as in the debugger, the instruction at the current PC always runs
+    let mut started = false;
+    let mut watch_hits = Vec::new();
+
+    mmu.borrow_mut().take_watch_hits();
+
+    let reply = loop {
+        if !ui.running {
+            // The target "exited"
+            break "W00".to_string();
+        }
+
+        if cpu.at_instruction_boundary() {
+            if started {
+                if let Some(hit) = watch_hits.first() {
+                    break format_watch_reply(hit);
+                }
+                if step || stub.breakpoints.contains(&cpu.reg.get16(R16::PC)) {
+                    break format!("S{:02x}", SIGTRAP);
+                }
+            }
+            started = true;
+        }
+
+        tick_gameboy(cpu, mmu, ppu);
+        if !stub.watchpoints.is_empty() {
+            watch_hits.append(&mut mmu.borrow_mut().take_watch_hits());
+        }
+        if ppu.take_frame_ready() {
+            update_display(cpu, ppu);
+            ui.draw_frame(0, &ppu.get_frame());
+        }
+
+        if last_render_time.elapsed() >= render_timer_period {
+            process_inputs(ui, mmu);
+            ui.present();
+            last_render_time = Instant::now();
+
+            if poll_interrupt(stream)? {
+                break format!("S{:02x}", SIGINT);
+            }
+        }
+    };
+
+    update_display(cpu, ppu);
+    Ok(reply)
+}
+
+/// Checks whether GDB sent an interrupt, without blocking.
+fn poll_interrupt(stream: &mut TcpStream) -> io::Result<bool> {
+    stream.set_nonblocking(true)?;
+    let mut byte = [0];
+    let result = stream.read(&mut byte);
+    stream.set_nonblocking(false)?;
+
+    match result {
+        Ok(0) => Err(io::Error::from(ErrorKind::UnexpectedEof)),
+        Ok(_) => Ok(byte[0] == INTERRUPT_BYTE),
+        Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+fn format_watch_reply(hit: &WatchHit) -> String {
+    let kind = match hit.watchpoint.kind {
+        WatchKind::Write => "watch",
+        WatchKind::Read => "rwatch",
+        WatchKind::Access => "awatch",
+    };
+    format!("T{:02x}{}:{:04x};", SIGTRAP, kind, hit.watchpoint.addr)
+}
+
+fn ok_or_error(ok: bool) -> String {
+    if ok { "OK".to_string() } else { error_reply() }
+}
+
+/// None means the request isn't supported, which gets the empty reply.
+fn ok_or_empty(result: Option<bool>) -> String {
+    result.map(ok_or_error).unwrap_or_default()
+}
+
+fn error_reply() -> String {
+    "E01".to_string()
+}
+
+fn parse_hex_u16(text: &str) -> Option<u16> {
+    u16::from_str_radix(text, 16).ok()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_stub() -> (GdbStub, Rc<RefCell<Mmu>>, Cpu) {
+        let (mmu, cpu, _ppu) = create_gameboy_components();
+        let stub = GdbStub {
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+        };
+        (stub, mmu, cpu)
+    }
+
+    fn reply(packet: &str, stub: &mut GdbStub, cpu: &mut Cpu, mmu: &Rc<RefCell<Mmu>>) -> String {
+        match handle_packet(packet, stub, cpu, mmu) {
+            Action::Reply(reply) => reply,
+            _ => panic!("Expected a reply to \"{}\"", packet),
+        }
+    }
+
+    #[test]
+    fn test_format_packet() {
+        assert_eq!(format_packet("OK"), "$OK#9a");
+        assert_eq!(format_packet(""), "$#00");
+        assert_eq!(decode_hex("00ff1a"), Some(vec![0x00, 0xFF, 0x1A]));
+        assert_eq!(decode_hex("0"), None);
+    }
+
+    #[test]
+    fn test_registers() {
+        let (mut stub, mmu, mut cpu) = create_stub();
+        cpu.reg.set16(R16::BC, 0x1234);
+        cpu.reg.set16(R16::PC, 0x0150);
+
+        let registers = reply("g", &mut stub, &mut cpu, &mmu);
+        assert_eq!(&registers[4..8], "3412");
+        assert_eq!(&registers[20..24], "5001");
+
+        assert_eq!(reply("P5=0002", &mut stub, &mut cpu, &mmu), "OK");
+        assert_eq!(cpu.reg.get16(R16::PC), 0x0200);
+        assert_eq!(reply("p5", &mut stub, &mut cpu, &mmu), "0002");
+        assert_eq!(reply("p6", &mut stub, &mut cpu, &mmu), "E01");
+
+        let packet = format!("G{}", "00".repeat(10) + "cdab");
+        assert_eq!(reply(&packet, &mut stub, &mut cpu, &mmu), "OK");
+        assert_eq!(cpu.reg.get16(R16::PC), 0xABCD);
+        assert_eq!(cpu.reg.get16(R16::BC), 0x0000);
+    }
+
+    #[test]
+    fn test_memory() {
+        let (mut stub, mmu, mut cpu) = create_stub();
+
+        assert_eq!(reply("Mc000,3:0102ff", &mut stub, &mut cpu, &mmu), "OK");
+        assert_eq!(mmu.borrow().read_byte_override(0xC002), 0xFF);
+        assert_eq!(reply("mc000,3", &mut stub, &mut cpu, &mmu), "0102ff");
+        assert_eq!(reply("Mc000,2:01", &mut stub, &mut cpu, &mmu), "E01");
+    }
+
+    #[test]
+    fn test_breakpoints_and_watchpoints() {
+        let (mut stub, mmu, mut cpu) = create_stub();
+
+        assert_eq!(reply("Z0,150,1", &mut stub, &mut cpu, &mmu), "OK");
+        assert!(stub.breakpoints.contains(&0x0150));
+        assert_eq!(reply("z0,150,1", &mut stub, &mut cpu, &mmu), "OK");
+        assert!(stub.breakpoints.is_empty());
+
+        assert_eq!(reply("Z2,c000,2", &mut stub, &mut cpu, &mmu), "OK");
+        // As if the CPU had written it
+        mmu.borrow().check_watchpoints(0xC001, true, 0x42);
+        let hits = mmu.borrow_mut().take_watch_hits();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(format_watch_reply(&hits[0]), "T05watch:c001;");
+
+        assert_eq!(reply("z2,c000,2", &mut stub, &mut cpu, &mmu), "OK");
+        assert!(stub.watchpoints.is_empty());
+        assert_eq!(reply("Z9,c000,1", &mut stub, &mut cpu, &mmu), "");
+    }
+
+    #[test]
+    fn test_run_control() {
+        let (mut stub, mmu, mut cpu) = create_stub();
+
+        assert_eq!(handle_packet("s", &mut stub, &mut cpu, &mmu), Action::Step);
+        assert_eq!(
+            handle_packet("c150", &mut stub, &mut cpu, &mmu),
+            Action::Continue
+        );
+        assert_eq!(cpu.reg.get16(R16::PC), 0x0150);
+        assert_eq!(
+            handle_packet("D", &mut stub, &mut cpu, &mmu),
+            Action::Disconnect(Some("OK".to_string()))
+        );
+        assert_eq!(reply("?", &mut stub, &mut cpu, &mmu), "S05");
+        assert_eq!(
+            reply(
+                "qXfer:features:read:target.xml:0,5",
+                &mut stub,
+                &mut cpu,
+                &mmu
+            ),
+            "m<?xml"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Debugger commands are read from the terminal, with line editing and history, or from
+//! script files (`source <file>` or `--debug-script <file>`). Script commands run before
+//! anything else is read from the terminal, so a script can set up a session and then hand
+//! control back to the user.
+
+use rustyline::{DefaultEditor, error::ReadlineError};
+use std::{collections::VecDeque, io, path::Path};
+
+const PROMPT: &str = "> ";
+
+pub struct CommandInput {
+    /// None if the terminal couldn't be set up for line editing
+    editor: Option<DefaultEditor>,
+    script_commands: VecDeque<String>,
+    last_command: Option<String>,
+}
+
+impl CommandInput {
+    pub fn new() -> Self {
+        let editor = match DefaultEditor::new() {
+            Ok(editor) => Some(editor),
+            Err(error) => {
+                println!("Line editing is unavailable: {}", error);
+                None
+            }
+        };
+
+        CommandInput {
+            editor,
+            script_commands: VecDeque::new(),
+            last_command: None,
+        }
+    }
+
+    /// Queues up the commands in a script, to run before any others that are already queued.
+    /// This way, scripts can source other scripts.
+    pub fn source(&mut self, path: &Path) -> io::Result<()> {
+        let text = std::fs::read_to_string(path)?;
+        for command in parse_script(&text).into_iter().rev() {
+            self.script_commands.push_front(command);
+        }
+        Ok(())
+    }
+
+    /// Returns None once there is nothing left to read (e.g. Ctrl-D).
+    /// An empty line repeats the last command.
+    pub fn next_command(&mut self) -> Option<String> {
+        if let Some(command) = self.script_commands.pop_front() {
+            println!("{}{}", PROMPT, command);
+            self.last_command = Some(command.clone());
+            return Some(command);
+        }
+
+        loop {
+            let line = self.read_line()?;
+            let line = line.trim();
+
+            if line.is_empty() {
+                match &self.last_command {
+                    Some(command) => return Some(command.clone()),
+                    None => continue,
+                }
+            }
+
+            if let Some(editor) = &mut self.editor {
+                let _ = editor.add_history_entry(line);
+            }
+            self.last_command = Some(line.to_string());
+            return Some(line.to_string());
+        }
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        let Some(editor) = &mut self.editor else {
+            let mut line = String::new();
+            return match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line),
+            };
+        };
+
+        loop {
+            match editor.readline(PROMPT) {
+                Ok(line) => return Some(line),
+                // Ctrl-C just abandons the line
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return None,
+                Err(error) => {
+                    println!("Failed to read input: {}", error);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Scripts have one command per line. Blank lines, and comments starting with `#`, are skipped.
+fn parse_script(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_commands() {
+        let mut input = CommandInput {
+            editor: None,
+            script_commands: VecDeque::new(),
+            last_command: None,
+        };
+        // Sourced commands run before the ones that were already queued
+        input.script_commands.push_back("bt".to_string());
+        let path = std::env::temp_dir().join("gameboy_debugger_test_script.txt");
+        std::fs::write(&path, "# Setup\nb Main\n\n  c  \n").unwrap();
+        input.source(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(input.next_command().as_deref(), Some("b Main"));
+        assert_eq!(input.next_command().as_deref(), Some("c"));
+        assert_eq!(input.last_command.as_deref(), Some("c"));
+        assert_eq!(input.next_command().as_deref(), Some("bt"));
+    }
+}
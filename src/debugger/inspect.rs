@@ -0,0 +1,174 @@
+//! Decoded views of the hardware registers, for the debugger.
+//! Like the memory commands, these read through the override functions so that looking at
+//! a register never changes it.
+
+use crate::cpu::Cpu;
+use crate::mmu::{Mmu, memmap::*};
+use crate::util::get_bit;
+
+const INTERRUPT_NAMES: [(u8, &str); 5] = [
+    (VBLANK_INTERRUPT_BIT, "VBlank"),
+    (STAT_INTERRUPT_BIT, "STAT"),
+    (TIMER_INTERRUPT_BIT, "Timer"),
+    (SERIAL_INTERRUPT_BIT, "Serial"),
+    (JOYPAD_INTERRUPT_BIT, "Joypad"),
+];
+
+const LCDC_BIT_NAMES: [(u8, &str); 8] = [
+    (LCD_AND_PPU_ENABLE_BIT, "LCD and PPU enable"),
+    (WINDOW_TILE_MAP_BIT, "Window tile map 9c00"),
+    (WINDOW_ENABLE_BIT, "Window enable"),
+    (BG_AND_WINDOW_TILES_BIT, "BG and window tiles 8000"),
+    (BG_TILE_MAP_BIT, "BG tile map 9c00"),
+    (OBJ_SIZE_BIT, "OBJ size 8x16"),
+    (OBJ_ENABLE_BIT, "OBJ enable"),
+    (BG_AND_WINDOW_ENABLE_BIT, "BG and window enable"),
+];
+
+const STAT_BIT_NAMES: [(u8, &str); 5] = [
+    (LYC_INT_SELECT_BIT, "LYC interrupt select"),
+    (MODE_2_INT_SELECT_BIT, "Mode 2 interrupt select"),
+    (MODE_1_INT_SELECT_BIT, "Mode 1 interrupt select"),
+    (MODE_0_INT_SELECT_BIT, "Mode 0 interrupt select"),
+    (LY_EQUALS_LYC_BIT, "LY == LYC"),
+];
+
+const MODE_NAMES: [&str; 4] = ["HBlank", "VBlank", "OAM scan", "Pixel draw"];
+
+const APU_REGISTERS: [(u16, &str); 21] = [
+    (NR_10_ADDR, "NR10"),
+    (NR_11_ADDR, "NR11"),
+    (NR_12_ADDR, "NR12"),
+    (NR_13_ADDR, "NR13"),
+    (NR_14_ADDR, "NR14"),
+    (NR_21_ADDR, "NR21"),
+    (NR_22_ADDR, "NR22"),
+    (NR_23_ADDR, "NR23"),
+    (NR_24_ADDR, "NR24"),
+    (NR_30_ADDR, "NR30"),
+    (NR_31_ADDR, "NR31"),
+    (NR_32_ADDR, "NR32"),
+    (NR_33_ADDR, "NR33"),
+    (NR_34_ADDR, "NR34"),
+    (NR_41_ADDR, "NR41"),
+    (NR_42_ADDR, "NR42"),
+    (NR_43_ADDR, "NR43"),
+    (NR_44_ADDR, "NR44"),
+    (NR_50_ADDR, "NR50"),
+    (NR_51_ADDR, "NR51"),
+    (NR_52_ADDR, "NR52"),
+];
+
+pub fn print_interrupts(cpu: &Cpu, mmu: &Mmu) {
+    let (ime, ime_pending) = cpu.get_ime();
+    let ie = mmu.read_byte_override(IE_ADDR);
+    let if_byte = mmu.read_byte_override(IF_ADDR);
+
+    let pending = if ime_pending { " (EI pending)" } else { "" };
+    println!("IME: {}{}", ime, pending);
+    println!("Halted: {}, stopped: {}", cpu.is_halted(), cpu.is_stopped());
+    for line in format_interrupts(ie, if_byte) {
+        println!("{}", line);
+    }
+}
+
+fn format_interrupts(ie: u8, if_byte: u8) -> Vec<String> {
+    let mut lines = vec![format!("IE: {:02x}  IF: {:02x}", ie, if_byte)];
+    for (bit, name) in INTERRUPT_NAMES {
+        let enabled = if get_bit(ie, bit) { "enabled" } else { "-" };
+        let requested = if get_bit(if_byte, bit) {
+            "requested"
+        } else {
+            "-"
+        };
+        lines.push(format!("  {:<8}{:<10}{}", name, enabled, requested));
+    }
+    lines
+}
+
+pub fn print_lcd(mmu: &Mmu) {
+    let read = |addr| mmu.read_byte_override(addr);
+    for line in format_bits("LCDC", read(LCDC_ADDR), &LCDC_BIT_NAMES) {
+        println!("{}", line);
+    }
+
+    let stat = read(STAT_ADDR);
+    for line in format_bits("STAT", stat, &STAT_BIT_NAMES) {
+        println!("{}", line);
+    }
+    println!(
+        "  Mode {} ({})",
+        stat & 0b11,
+        MODE_NAMES[(stat & 0b11) as usize]
+    );
+
+    println!("LY: {:3}  LYC: {:3}", read(LY_ADDR), read(LYC_ADDR));
+    println!("SCX: {:3}  SCY: {:3}", read(SCX_ADDR), read(SCY_ADDR));
+    println!("WX: {:3}  WY: {:3}", read(WX_ADDR), read(WY_ADDR));
+    println!(
+        "BGP: {:02x}  OBP0: {:02x}  OBP1: {:02x}",
+        read(BGP_ADDR),
+        read(OBP0_ADDR),
+        read(OBP1_ADDR)
+    );
+}
+
+/// Lists the register, then each of its named bits that are set.
+fn format_bits(name: &str, byte: u8, bit_names: &[(u8, &str)]) -> Vec<String> {
+    let mut lines = vec![format!("{}: {:02x}", name, byte)];
+    for (bit, bit_name) in bit_names {
+        if get_bit(byte, *bit) {
+            lines.push(format!("  {}", bit_name));
+        }
+    }
+    lines
+}
+
+/// There's no APU yet, so this only shows what has been written to its registers.
+pub fn print_apu(mmu: &Mmu) {
+    for row in APU_REGISTERS.chunks(5) {
+        let registers: Vec<String> = row
+            .iter()
+            .map(|(addr, name)| format!("{}: {:02x}", name, mmu.read_byte_override(*addr)))
+            .collect();
+        println!("{}", registers.join("  "));
+    }
+
+    let nr52 = mmu.read_byte_override(NR_52_ADDR);
+    let channels: Vec<String> = (0..4)
+        .filter(|channel| get_bit(nr52, *channel))
+        .map(|channel| (channel + 1).to_string())
+        .collect();
+    println!(
+        "Audio {}, channels on: {}",
+        if get_bit(nr52, 7) { "on" } else { "off" },
+        if channels.is_empty() {
+            "none".to_string()
+        } else {
+            channels.join(", ")
+        }
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_registers() {
+        let lines = format_interrupts(0b0_0101, 0b0_0001);
+        assert_eq!(lines[0], "IE: 05  IF: 01");
+        assert_eq!(lines[1], "  VBlank  enabled   requested");
+        assert_eq!(lines[2], "  STAT    -         -");
+
+        assert_eq!(
+            format_bits("LCDC", 0x91, &LCDC_BIT_NAMES),
+            vec![
+                "LCDC: 91",
+                "  LCD and PPU enable",
+                "  BG and window tiles 8000",
+                "  BG and window enable"
+            ]
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! Memory inspection and editing for the debugger.
+//! Everything here goes through the override functions, so it sees exactly what is mapped
+//! into memory right now (including the current ROM bank), without tripping VRAM/OAM locks
+//! or the side-effects of writing to IO registers.
+
+use crate::mmu::Mmu;
+
+const BYTES_PER_LINE: u16 = 16;
+
+pub fn print_memory(mmu: &Mmu, start_addr: u16, len: u16) {
+    let mut addr = start_addr;
+    let mut remaining = len as u32;
+
+    while remaining > 0 {
+        let line_len = remaining.min(BYTES_PER_LINE as u32) as u16;
+        let bytes: Vec<u8> = (0..line_len)
+            .map(|offset| mmu.read_byte_override(addr.wrapping_add(offset)))
+            .collect();
+        println!("{}", format_line(addr, &bytes));
+
+        addr = addr.wrapping_add(line_len);
+        remaining -= line_len as u32;
+    }
+}
+
+/// Formats one line of a memory dump, e.g. `c000: 48 69 00  |Hi.|`
+fn format_line(addr: u16, bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let ascii: String = bytes
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+
+    // Pad the hex column so that the ASCII column lines up on short lines
+    let hex_width = BYTES_PER_LINE as usize * 3 - 1;
+    format!("{:04x}: {:<hex_width$}  |{}|", addr, hex.join(" "), ascii)
+}
+
+pub fn fill_memory(mmu: &mut Mmu, start_addr: u16, len: u16, byte: u8) {
+    for offset in 0..len {
+        mmu.write_byte_override(start_addr.wrapping_add(offset), byte);
+    }
+}
+
+/// Returns the address of every occurrence of the pattern in the address space.
+pub fn find_bytes(mmu: &Mmu, pattern: &[u8]) -> Vec<u16> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+
+    let memory: Vec<u8> = (0..=u16::MAX).map(|addr| mmu.read_byte_override(addr)).collect();
+    memory
+        .windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| *window == pattern)
+        .map(|(addr, _)| addr as u16)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_line() {
+        assert_eq!(
+            format_line(0xC000, &[0x48, 0x69, 0x00]),
+            format!("c000: 48 69 00{}  |Hi.|", " ".repeat(39))
+        );
+    }
+
+    #[test]
+    fn test_fill_and_find() {
+        let mmu = Mmu::new();
+        let mut mmu = mmu.borrow_mut();
+
+        fill_memory(&mut mmu, 0xC100, 4, 0xAB);
+        mmu.write_byte_override(0xC104, 0xCD);
+
+        // WRAM is mirrored by echo RAM, so the pattern shows up twice
+        assert_eq!(find_bytes(&mmu, &[0xAB, 0xCD]), vec![0xC103, 0xE103]);
+        assert!(find_bytes(&mmu, &[0xAB, 0xAB, 0xAB, 0xAB, 0xAB]).is_empty());
+    }
+}
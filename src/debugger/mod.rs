@@ -0,0 +1,854 @@
+mod disasm;
+mod expr;
+mod gdb;
+mod input;
+mod inspect;
+mod memory;
+pub mod symbols;
+
+use crate::cpu::{
+    call_stack::{CallFrame, CallKind},
+    registers::R8,
+};
+use crate::logging;
+use crate::ppu::{SCREEN_HEIGHT, T_CYCLES_PER_FRAME};
+use crate::screenshot::save_screenshot;
+use crate::mmu::{
+    memmap::{PROGRAM_START_ADDR, TOP_OF_STACK_ADDRESS},
+    watchpoints::{WatchHit, WatchKind, Watchpoint},
+};
+use expr::{Expr, parse_expr};
+use input::CommandInput;
+pub use gdb::run_gdb_server;
+use std::{collections::BTreeMap, path::Path};
+use symbols::Symbols;
+
+use super::*;
+
+const DEFAULT_DISASSEMBLY_LINES: u16 = 10;
+/// How long `step`, `frame` and `line` run before giving up, e.g. with the LCD off, or the CPU
+/// halted with no interrupts enabled. Long enough for the slowest timer interrupt to wake a HALT.
+const RUN_LIMIT_T_CYCLES: u64 = 8 * T_CYCLES_PER_FRAME as u64;
+
+enum DebugCommand {
+    Quit,
+    Step(u32),
+    Frame,
+    Line,
+    Until(u16),
+    PrintRegisters,
+    PrintVram,
+    PrintTimers,
+    PrintInterrupts,
+    PrintLcd,
+    PrintDma,
+    PrintApu,
+    PrintLogLevels,
+    SetLogLevels(String),
+    Examine(u16, u16),
+    SetByte(u16, u8),
+    Fill(u16, u16, u8),
+    Find(Vec<u8>),
+    SetRegister(Register, u16),
+    LoadSymbols(String),
+    Source(String),
+    Screenshot(String, Option<usize>),
+    Disassemble(Option<u16>, u16),
+    Break(u16, Option<Expr>),
+    Delete(u16),
+    Watch(Watchpoint, Option<Expr>),
+    Unwatch(u16),
+    PrintBreakpoints,
+    Print(Expr),
+    Continue,
+    Next,
+    Finish,
+    Backtrace,
+    ToggleTrace,
+    Invalid(String),
+    None,
+}
+
+/// Watchpoints themselves live in the MMU, since that's where the accesses happen.
+struct Watch {
+    watchpoint: Watchpoint,
+    condition: Option<Expr>,
+}
+
+/// Everything the debugger keeps track of between commands.
+struct DebugState {
+    symbols: Symbols,
+    /// Breakpoints only stop execution if their condition (if any) is true
+    breakpoints: BTreeMap<u16, Option<Expr>>,
+    watches: Vec<Watch>,
+    trace: bool,
+}
+
+pub fn run_debug(path: &str, options: &Options) {
+    println!("\nDebugging rom at: \"{}\"", path);
+
+    let (mmu, mut cpu, mut ppu) = create_gameboy_components();
+
+    cpu.reg.set16(R16::PC, PROGRAM_START_ADDR);
+    cpu.reg.set16(R16::SP, TOP_OF_STACK_ADDRESS);
+
+    if !mmu.borrow_mut().load_rom(path) {
+        println!("Failed to load rom at \"{}\"", path);
+        return;
+    }
+
+    emulate_boot(&mmu, &mut cpu);
+
+    let mut state = DebugState {
+        symbols: Symbols::new(),
+        breakpoints: BTreeMap::new(),
+        watches: Vec::new(),
+        trace: false,
+    };
+
+    // RGBDS puts the symbol file next to the ROM
+    let sym_path = Path::new(path).with_extension("sym");
+    if sym_path.exists() {
+        load_symbols(&mut state, &sym_path);
+    }
+
+    let mut commands = CommandInput::new();
+    if let Some(script) = &options.debug_script {
+        source_script(&mut commands, Path::new(script));
+    }
+
+    let mut ui = UserInterface::new(options);
+    ui.draw_frame(0, &ppu.get_frame());
+    let mut running = true;
+
+    while running {
+        process_inputs(&mut ui, &mmu);
+        ui.present();
+
+        let Some(input) = commands.next_command() else {
+            break;
+        };
+        let command = parse_user_input(input, &state.symbols);
+
+        match command {
+            DebugCommand::Quit => running = false,
+            DebugCommand::Step(count) => {
+                let mut remaining = count;
+                let limit = Some(count as u64 * RUN_LIMIT_T_CYCLES);
+                run_until(&state, &mut cpu, &mmu, &mut ppu, &mut ui, limit, |_| {
+                    remaining -= 1;
+                    remaining == 0
+                });
+                if count != 1 {
+                    println!("Stepped {} instructions", count - remaining);
+                }
+                print_next_instruction(&state, &cpu);
+            }
+            // LY is checked between instructions, so these stop at the first instruction
+            // boundary after it changes (which may be a bit later when the CPU is halted)
+            DebugCommand::Frame => {
+                let mut previous_ly = read_ly(&mmu);
+                let limit = Some(RUN_LIMIT_T_CYCLES);
+                run_until(&state, &mut cpu, &mmu, &mut ppu, &mut ui, limit, |_| {
+                    let ly = read_ly(&mmu);
+                    let in_vblank = |ly: u8| ly >= SCREEN_HEIGHT as u8;
+                    let vblank_started = in_vblank(ly) && !in_vblank(previous_ly);
+                    previous_ly = ly;
+                    vblank_started
+                });
+                print_next_instruction(&state, &cpu);
+            }
+            DebugCommand::Line => {
+                let start_ly = read_ly(&mmu);
+                let limit = Some(RUN_LIMIT_T_CYCLES);
+                run_until(&state, &mut cpu, &mmu, &mut ppu, &mut ui, limit, |_| {
+                    read_ly(&mmu) != start_ly
+                });
+                print_next_instruction(&state, &cpu);
+            }
+            DebugCommand::Until(addr) => {
+                run_until(&state, &mut cpu, &mmu, &mut ppu, &mut ui, None, |cpu| {
+                    cpu.reg.get16(R16::PC) == addr
+                });
+                print_next_instruction(&state, &cpu);
+            }
+            DebugCommand::PrintVram => mmu.borrow().print_vram(),
+            DebugCommand::PrintRegisters => cpu.reg.print(),
+            DebugCommand::PrintTimers => mmu.borrow().print_timers(),
+            DebugCommand::PrintInterrupts => inspect::print_interrupts(&cpu, &mmu.borrow()),
+            DebugCommand::PrintLcd => inspect::print_lcd(&mmu.borrow()),
+            DebugCommand::PrintDma => mmu.borrow().print_dma(),
+            DebugCommand::PrintApu => inspect::print_apu(&mmu.borrow()),
+            DebugCommand::PrintLogLevels => logging::print_levels(),
+            DebugCommand::SetLogLevels(spec) => {
+                if !logging::set_levels(&spec) {
+                    println!("Invalid log levels \"{}\"", spec);
+                }
+            }
+            DebugCommand::Examine(addr, len) => memory::print_memory(&mmu.borrow(), addr, len),
+            DebugCommand::SetByte(addr, byte) => mmu.borrow_mut().write_byte_override(addr, byte),
+            DebugCommand::Fill(addr, len, byte) => {
+                memory::fill_memory(&mut mmu.borrow_mut(), addr, len, byte)
+            }
+            DebugCommand::Find(pattern) => {
+                let matches = memory::find_bytes(&mmu.borrow(), &pattern);
+                for addr in &matches {
+                    println!("{:04x}", addr);
+                }
+                println!("{} matches", matches.len());
+            }
+            DebugCommand::SetRegister(register, value) => match register {
+                Register::R8(r8) => cpu.reg.set(r8, value as u8),
+                Register::R16(r16) => cpu.reg.set16(r16, value),
+            },
+            DebugCommand::LoadSymbols(path) => load_symbols(&mut state, Path::new(&path)),
+            DebugCommand::Source(path) => source_script(&mut commands, Path::new(&path)),
+            DebugCommand::Screenshot(path, scale) => {
+                let scale = scale.or(options.screenshot_scale).unwrap_or(1);
+                let frame = ppu.get_frame();
+                match save_screenshot(Path::new(&path), &frame, &ui.palette, scale) {
+                    Ok(()) => println!("Saved screenshot to \"{}\"", path),
+                    Err(error) => println!("Failed to save screenshot to \"{}\": {}", path, error),
+                }
+            }
+            DebugCommand::Disassemble(addr, count) => {
+                let addr = addr.unwrap_or(cpu.reg.get16(R16::PC));
+                print_disassembly(&state.symbols, &mmu.borrow(), addr, count);
+            }
+            DebugCommand::Break(addr, condition) => {
+                state.breakpoints.insert(addr, condition);
+                println!("Breakpoint at {}", state.symbols.format_addr(addr));
+            }
+            DebugCommand::Delete(addr) => {
+                if state.breakpoints.remove(&addr).is_none() {
+                    println!("No breakpoint at {}", state.symbols.format_addr(addr));
+                }
+            }
+            DebugCommand::Watch(watchpoint, condition) => {
+                mmu.borrow_mut().add_watchpoint(watchpoint);
+                state.watches.retain(|watch| watch.watchpoint != watchpoint);
+                state.watches.push(Watch { watchpoint, condition });
+                println!("Watchpoint at {}", state.symbols.format_addr(watchpoint.addr));
+            }
+            DebugCommand::Unwatch(addr) => {
+                let len = state.watches.len();
+                state.watches.retain(|watch| {
+                    let keep = watch.watchpoint.addr != addr;
+                    if !keep {
+                        mmu.borrow_mut().remove_watchpoint(watch.watchpoint);
+                    }
+                    keep
+                });
+                if state.watches.len() == len {
+                    println!("No watchpoint at {}", state.symbols.format_addr(addr));
+                }
+            }
+            DebugCommand::PrintBreakpoints => print_breakpoints(&state),
+            DebugCommand::Print(expr) => {
+                let value = expr.eval(&cpu);
+                println!("{} (${:x})", value, value);
+            }
+            DebugCommand::Continue => {
+                run_until(&state, &mut cpu, &mmu, &mut ppu, &mut ui, None, |_| false);
+                print_next_instruction(&state, &cpu);
+            }
+            // Stepping over a call is the same as stepping an instruction,
+            // but without stopping anywhere deeper in the call stack
+            DebugCommand::Next => {
+                let depth = cpu.call_stack().len();
+                run_until(&state, &mut cpu, &mmu, &mut ppu, &mut ui, None, |cpu| {
+                    cpu.call_stack().len() <= depth
+                });
+                print_next_instruction(&state, &cpu);
+            }
+            DebugCommand::Finish => {
+                let depth = cpu.call_stack().len();
+                if depth == 0 {
+                    println!("Not inside of a call");
+                } else {
+                    run_until(&state, &mut cpu, &mmu, &mut ppu, &mut ui, None, |cpu| {
+                        cpu.call_stack().len() < depth
+                    });
+                    print_next_instruction(&state, &cpu);
+                }
+            }
+            DebugCommand::Backtrace => print_backtrace(&state.symbols, &cpu),
+            DebugCommand::ToggleTrace => {
+                state.trace = !state.trace;
+                println!("Trace {}", if state.trace { "on" } else { "off" });
+            }
+            DebugCommand::Invalid(error) => println!("{}", error),
+            DebugCommand::None => println!("Unrecognized Command"),
+        }
+    }
+}
+
+fn parse_user_input(inputs: String, symbols: &Symbols) -> DebugCommand {
+    let mut args = inputs
+        .split_whitespace()
+        .map(|str| str.to_string())
+        .collect::<Vec<String>>();
+
+    args.reverse(); // Reverse args so popping from the back yields them in order
+
+    let arg = args.pop();
+    if arg.is_none() {
+        return DebugCommand::None;
+    }
+
+    // Map inputs to commands
+    match arg.unwrap().to_lowercase().as_str() {
+        "q" | "quit" => DebugCommand::Quit,
+        "n" | "step" | "stepi" => parse_step_arg(args),
+        "frame" => DebugCommand::Frame,
+        "line" => DebugCommand::Line,
+        "until" => parse_address_arg(args, symbols, DebugCommand::Until),
+        "r" | "reg" => parse_reg_args(args),
+        "m" | "vram" => DebugCommand::PrintVram,
+        "t" | "timer" => DebugCommand::PrintTimers,
+        "int" | "interrupts" => DebugCommand::PrintInterrupts,
+        "lcd" => DebugCommand::PrintLcd,
+        "dma" => DebugCommand::PrintDma,
+        "apu" => DebugCommand::PrintApu,
+        "l" | "log" => parse_log_args(args),
+        "x" => parse_examine_args(args, symbols),
+        "set" => parse_set_args(args, symbols),
+        "fill" => parse_fill_args(args, symbols),
+        "find" => parse_find_args(args),
+        "sym" => match args.pop() {
+            Some(path) => DebugCommand::LoadSymbols(path),
+            None => DebugCommand::None,
+        },
+        "source" => match args.pop() {
+            Some(path) => DebugCommand::Source(path),
+            None => DebugCommand::None,
+        },
+        "screenshot" => parse_screenshot_args(args),
+        "dis" => parse_disassemble_args(args, symbols),
+        "b" | "break" => parse_break_args(args, symbols),
+        "d" | "delete" => parse_address_arg(args, symbols, DebugCommand::Delete),
+        "w" | "watch" => parse_watch_args(args, symbols),
+        "unwatch" => parse_address_arg(args, symbols, DebugCommand::Unwatch),
+        "p" | "print" => match parse_expr(&join_args(args), symbols) {
+            Ok(expr) => DebugCommand::Print(expr),
+            Err(error) => DebugCommand::Invalid(error),
+        },
+        "bl" | "breakpoints" => DebugCommand::PrintBreakpoints,
+        "c" | "continue" => DebugCommand::Continue,
+        "next" => DebugCommand::Next,
+        "finish" => DebugCommand::Finish,
+        "bt" | "backtrace" => DebugCommand::Backtrace,
+        "trace" => DebugCommand::ToggleTrace,
+
+        _ => DebugCommand::None,
+    }
+}
+
+/// `screenshot <file> [scale]`
+fn parse_screenshot_args(mut args: Vec<String>) -> DebugCommand {
+    let Some(path) = args.pop() else {
+        return DebugCommand::Invalid("Usage: screenshot <file> [scale]".to_string());
+    };
+    match args.pop().map(|scale| scale.parse::<usize>()) {
+        None => DebugCommand::Screenshot(path, None),
+        Some(Ok(scale)) if scale > 0 => DebugCommand::Screenshot(path, Some(scale)),
+        Some(_) => DebugCommand::Invalid("Invalid scale".to_string()),
+    }
+}
+
+fn parse_step_arg(mut args: Vec<String>) -> DebugCommand {
+    let arg = args.pop();
+    if arg.is_none() {
+        return DebugCommand::Step(1);
+    }
+
+    // Stepping 0 instructions would never stop
+    let steps: Option<u32> = arg.unwrap().parse().ok().filter(|&steps| steps > 0);
+
+    if let Some(value) = steps {
+        DebugCommand::Step(value)
+    } else {
+        DebugCommand::Step(1)
+    }
+}
+
+/// `log` prints the current levels, `log <level>` sets every category,
+/// and `log <category> <level>` sets just one. Full specs (`log ppu=trace,dma=debug`) work too.
+fn parse_log_args(mut args: Vec<String>) -> DebugCommand {
+    match (args.pop(), args.pop()) {
+        (None, _) => DebugCommand::PrintLogLevels,
+        (Some(spec), None) => DebugCommand::SetLogLevels(spec),
+        (Some(category), Some(level)) => DebugCommand::SetLogLevels(format!("{}={}", category, level)),
+    }
+}
+
+/// Addresses and values are written in hex, with an optional `0x` or `$` prefix.
+fn parse_hex(arg: &str) -> Option<u16> {
+    let digits = arg
+        .strip_prefix("0x")
+        .or_else(|| arg.strip_prefix('$'))
+        .unwrap_or(arg);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// Addresses can also be given as a label from the symbol file.
+fn parse_address(arg: &str, symbols: &Symbols) -> Option<u16> {
+    symbols.get_addr(arg).or_else(|| parse_hex(arg))
+}
+
+fn parse_address_arg(
+    mut args: Vec<String>,
+    symbols: &Symbols,
+    command: fn(u16) -> DebugCommand,
+) -> DebugCommand {
+    match args.pop().and_then(|arg| parse_address(&arg, symbols)) {
+        Some(addr) => command(addr),
+        None => DebugCommand::None,
+    }
+}
+
+fn parse_hex_byte(arg: &str) -> Option<u8> {
+    parse_hex(arg).and_then(|value| u8::try_from(value).ok())
+}
+
+#[derive(Clone, Copy)]
+enum Register {
+    R8(R8),
+    R16(R16),
+}
+
+fn parse_register(name: &str) -> Option<Register> {
+    let register = match name.to_lowercase().as_str() {
+        "a" => Register::R8(R8::A),
+        "b" => Register::R8(R8::B),
+        "c" => Register::R8(R8::C),
+        "d" => Register::R8(R8::D),
+        "e" => Register::R8(R8::E),
+        "f" => Register::R8(R8::F),
+        "h" => Register::R8(R8::H),
+        "l" => Register::R8(R8::L),
+        "af" => Register::R16(R16::AF),
+        "bc" => Register::R16(R16::BC),
+        "de" => Register::R16(R16::DE),
+        "hl" => Register::R16(R16::HL),
+        "sp" => Register::R16(R16::SP),
+        "pc" => Register::R16(R16::PC),
+        _ => return None,
+    };
+    Some(register)
+}
+
+/// `x <addr> [len]`
+fn parse_examine_args(mut args: Vec<String>, symbols: &Symbols) -> DebugCommand {
+    const DEFAULT_EXAMINE_LEN: u16 = 0x40;
+
+    let addr = args.pop().and_then(|arg| parse_address(&arg, symbols));
+    let len = match args.pop() {
+        Some(arg) => parse_hex(&arg),
+        None => Some(DEFAULT_EXAMINE_LEN),
+    };
+
+    match (addr, len) {
+        (Some(addr), Some(len)) => DebugCommand::Examine(addr, len),
+        _ => DebugCommand::None,
+    }
+}
+
+/// `set <addr> <value>`
+fn parse_set_args(mut args: Vec<String>, symbols: &Symbols) -> DebugCommand {
+    let addr = args.pop().and_then(|arg| parse_address(&arg, symbols));
+    let byte = args.pop().and_then(|arg| parse_hex_byte(&arg));
+
+    match (addr, byte) {
+        (Some(addr), Some(byte)) => DebugCommand::SetByte(addr, byte),
+        _ => DebugCommand::None,
+    }
+}
+
+/// `fill <addr> <len> <value>`
+fn parse_fill_args(mut args: Vec<String>, symbols: &Symbols) -> DebugCommand {
+    let addr = args.pop().and_then(|arg| parse_address(&arg, symbols));
+    let len = args.pop().and_then(|arg| parse_hex(&arg));
+    let byte = args.pop().and_then(|arg| parse_hex_byte(&arg));
+
+    match (addr, len, byte) {
+        (Some(addr), Some(len), Some(byte)) => DebugCommand::Fill(addr, len, byte),
+        _ => DebugCommand::None,
+    }
+}
+
+/// `find <bytes>`, where the bytes can be separate (`find 3e 01`) or joined (`find 3e01`).
+fn parse_find_args(args: Vec<String>) -> DebugCommand {
+    let digits: String = args.into_iter().rev().collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return DebugCommand::None;
+    }
+
+    let pattern: Option<Vec<u8>> = (0..digits.len())
+        .step_by(2)
+        .map(|i| digits.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect();
+
+    match pattern {
+        Some(pattern) => DebugCommand::Find(pattern),
+        None => DebugCommand::None,
+    }
+}
+
+/// Joins the remaining args back together, e.g. for an expression that contains spaces.
+fn join_args(mut args: Vec<String>) -> String {
+    args.reverse();
+    args.join(" ")
+}
+
+/// Parses the optional `if <expr>` at the end of a breakpoint or watchpoint.
+fn parse_condition(mut args: Vec<String>, symbols: &Symbols) -> Result<Option<Expr>, String> {
+    match args.pop() {
+        None => Ok(None),
+        Some(arg) if arg.eq_ignore_ascii_case("if") => parse_expr(&join_args(args), symbols).map(Some),
+        Some(arg) => Err(format!("Expected \"if\", found \"{}\"", arg)),
+    }
+}
+
+/// `break <addr> [if <expr>]`
+fn parse_break_args(mut args: Vec<String>, symbols: &Symbols) -> DebugCommand {
+    let Some(addr) = args.pop().and_then(|arg| parse_address(&arg, symbols)) else {
+        return DebugCommand::None;
+    };
+
+    match parse_condition(args, symbols) {
+        Ok(condition) => DebugCommand::Break(addr, condition),
+        Err(error) => DebugCommand::Invalid(error),
+    }
+}
+
+/// `watch <addr> [r|w|rw] [if <expr>]` watches for writes by default.
+fn parse_watch_args(mut args: Vec<String>, symbols: &Symbols) -> DebugCommand {
+    let Some(addr) = args.pop().and_then(|arg| parse_address(&arg, symbols)) else {
+        return DebugCommand::None;
+    };
+
+    let kind = match args.last().map(|arg| arg.to_lowercase()).as_deref() {
+        Some("r") => Some(WatchKind::Read),
+        Some("w") => Some(WatchKind::Write),
+        Some("rw") => Some(WatchKind::Access),
+        _ => None,
+    };
+    if kind.is_some() {
+        args.pop();
+    }
+    let watchpoint = Watchpoint {
+        addr,
+        kind: kind.unwrap_or(WatchKind::Write),
+    };
+
+    match parse_condition(args, symbols) {
+        Ok(condition) => DebugCommand::Watch(watchpoint, condition),
+        Err(error) => DebugCommand::Invalid(error),
+    }
+}
+
+/// `dis [addr] [count]` disassembles from PC by default.
+fn parse_disassemble_args(mut args: Vec<String>, symbols: &Symbols) -> DebugCommand {
+    let addr = match args.pop() {
+        Some(arg) => match parse_address(&arg, symbols) {
+            Some(addr) => Some(addr),
+            None => return DebugCommand::None,
+        },
+        None => None,
+    };
+    let count = match args.pop() {
+        Some(arg) => arg.parse().ok(),
+        None => Some(DEFAULT_DISASSEMBLY_LINES),
+    };
+
+    match count {
+        Some(count) => DebugCommand::Disassemble(addr, count),
+        None => DebugCommand::None,
+    }
+}
+
+/// `reg` prints the registers, `reg <name> <value>` edits one.
+fn parse_reg_args(mut args: Vec<String>) -> DebugCommand {
+    let Some(name) = args.pop() else {
+        return DebugCommand::PrintRegisters;
+    };
+
+    let register = parse_register(&name);
+    let value = args.pop().and_then(|arg| parse_hex(&arg));
+
+    match (register, value) {
+        (Some(Register::R8(r8)), Some(value)) if value <= u8::MAX as u16 => {
+            DebugCommand::SetRegister(Register::R8(r8), value)
+        }
+        (Some(Register::R16(r16)), Some(value)) => DebugCommand::SetRegister(Register::R16(r16), value),
+        _ => DebugCommand::None,
+    }
+}
+
+/// Runs the whole system until the stop condition is true at the start of an instruction.
+/// Breakpoints, closing the window, and running for more than `limit` t-cycles stop it early.
+fn run_until(
+    state: &DebugState,
+    cpu: &mut Cpu,
+    mmu: &Rc<RefCell<Mmu>>,
+    ppu: &mut Ppu,
+    ui: &mut UserInterface,
+    limit: Option<u64>,
+    mut stop: impl FnMut(&Cpu) -> bool,
+) {
+    let render_timer_period = Duration::from_secs_f64(1.0 / 60.0);
+    let mut last_render_time = Instant::now();
+
+    // The instruction at the current PC always runs, so that a breakpoint
+    // there (if there is one) doesn't stop execution right away
+    let mut started = false;
+    // Watchpoints are hit partway through an instruction, so they are checked after it finishes
+    let mut watch_hits = Vec::new();
+    let mut t_cycles: u64 = 0;
+
+    mmu.borrow_mut().take_watch_hits();
+
+    while ui.running {
+        if cpu.at_instruction_boundary() {
+            if started {
+                if check_watch_hits(state, cpu, &watch_hits) {
+                    break;
+                }
+                watch_hits.clear();
+
+                let pc = cpu.reg.get16(R16::PC);
+                if let Some(condition) = state.breakpoints.get(&pc)
+                    && condition.as_ref().is_none_or(|condition| condition.is_true(cpu))
+                {
+                    println!("Hit breakpoint at {}", state.symbols.format_addr(pc));
+                    break;
+                }
+                if stop(cpu) {
+                    break;
+                }
+            }
+            started = true;
+        }
+
+        if limit.is_some_and(|limit| t_cycles >= limit) {
+            let frames = t_cycles as f64 / T_CYCLES_PER_FRAME as f64;
+            println!(
+                "Gave up after {:.1} frames. Is the LCD off, or the CPU halted for good?",
+                frames
+            );
+            break;
+        }
+        t_cycles += 1;
+
+        trace_instruction(state, cpu);
+        tick_gameboy(cpu, mmu, ppu);
+        if !state.watches.is_empty() {
+            watch_hits.append(&mut mmu.borrow_mut().take_watch_hits());
+        }
+        if ppu.take_frame_ready() {
+            update_display(cpu, ppu);
+            ui.draw_frame(0, &ppu.get_frame());
+        }
+
+        if last_render_time.elapsed() >= render_timer_period {
+            process_inputs(ui, mmu);
+            ui.present();
+            last_render_time = Instant::now();
+        }
+    }
+    update_display(cpu, ppu);
+}
+
+fn read_ly(mmu: &Rc<RefCell<Mmu>>) -> u8 {
+    mmu.borrow().read_byte_override(LY_ADDR)
+}
+
+/// Prints every hit whose condition (if any) is true, and returns true if there were any.
+fn check_watch_hits(state: &DebugState, cpu: &Cpu, hits: &[WatchHit]) -> bool {
+    let mut triggered = false;
+
+    for hit in hits {
+        let Some(watch) = state.watches.iter().find(|w| w.watchpoint == hit.watchpoint) else {
+            continue;
+        };
+        if watch.condition.as_ref().is_some_and(|condition| !condition.is_true(cpu)) {
+            continue;
+        }
+
+        let access = if hit.write { "Write" } else { "Read" };
+        println!(
+            "Hit watchpoint: {} ${:02x} at {}",
+            access,
+            hit.value,
+            state.symbols.format_addr(hit.watchpoint.addr)
+        );
+        triggered = true;
+    }
+
+    triggered
+}
+
+fn print_breakpoints(state: &DebugState) {
+    for (addr, condition) in &state.breakpoints {
+        let conditional = if condition.is_some() { " (conditional)" } else { "" };
+        println!("Breakpoint at {}{}", state.symbols.format_addr(*addr), conditional);
+    }
+    for watch in &state.watches {
+        let conditional = if watch.condition.is_some() { " (conditional)" } else { "" };
+        println!(
+            "Watchpoint ({:?}) at {}{}",
+            watch.watchpoint.kind,
+            state.symbols.format_addr(watch.watchpoint.addr),
+            conditional
+        );
+    }
+}
+
+/// With tracing on, every instruction is printed right before it runs.
+fn trace_instruction(state: &DebugState, cpu: &Cpu) {
+    if state.trace && cpu.at_instruction_boundary() {
+        let pc = cpu.reg.get16(R16::PC);
+        let instruction = disasm::disassemble(&cpu.mmu.borrow(), &state.symbols, pc);
+        println!("{}", disasm::format_instruction(&instruction, &state.symbols));
+    }
+}
+
+fn print_next_instruction(state: &DebugState, cpu: &Cpu) {
+    let pc = cpu.reg.get16(R16::PC);
+    let instruction = disasm::disassemble(&cpu.mmu.borrow(), &state.symbols, pc);
+    println!("Next Instruction: {}", disasm::format_instruction(&instruction, &state.symbols));
+}
+
+/// Frames are printed innermost first, starting with the current PC.
+fn print_backtrace(symbols: &Symbols, cpu: &Cpu) {
+    println!("#0  {}", symbols.format_addr(cpu.reg.get16(R16::PC)));
+    for (i, frame) in cpu.call_stack().iter().rev().enumerate() {
+        println!("#{:<2} {}", i + 1, format_call_frame(symbols, frame));
+    }
+}
+
+fn format_call_frame(symbols: &Symbols, frame: &CallFrame) -> String {
+    let kind = match frame.kind {
+        CallKind::Call => "call",
+        CallKind::Rst => "rst",
+        CallKind::Interrupt => "interrupt",
+    };
+    format!(
+        "{} (bank {:02x}), {} to {}",
+        symbols.format_addr(frame.caller_pc),
+        frame.caller_bank,
+        kind,
+        symbols.format_addr(frame.target)
+    )
+}
+
+fn print_disassembly(symbols: &Symbols, mmu: &Mmu, start_addr: u16, count: u16) {
+    let mut addr = start_addr;
+    for _i in 0..count {
+        // Labels get their own line, like in the source
+        if let Some(label) = symbols.get_label(addr) {
+            println!("{}:", label);
+        }
+        let instruction = disasm::disassemble(mmu, symbols, addr);
+        println!("    {}", disasm::format_instruction(&instruction, symbols));
+        addr = addr.wrapping_add(instruction.len());
+    }
+}
+
+fn source_script(commands: &mut CommandInput, path: &Path) {
+    if let Err(error) = commands.source(path) {
+        println!("Failed to read script \"{}\": {}", path.display(), error);
+    }
+}
+
+fn load_symbols(state: &mut DebugState, path: &Path) {
+    match Symbols::load(path) {
+        Ok(symbols) => {
+            println!("Loaded {} symbols from \"{}\"", symbols.len(), path.display());
+            state.symbols = symbols;
+        }
+        Err(error) => println!("Failed to load symbols from \"{}\": {}", path.display(), error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> DebugCommand {
+        parse_user_input(input.to_string(), &Symbols::new())
+    }
+
+    #[test]
+    fn test_parse_memory_commands() {
+        assert!(matches!(parse("x $c000"), DebugCommand::Examine(0xC000, 0x40)));
+        assert!(matches!(parse("x 0xff80 10"), DebugCommand::Examine(0xFF80, 0x10)));
+        assert!(matches!(parse("set c000 ff"), DebugCommand::SetByte(0xC000, 0xFF)));
+        assert!(matches!(parse("set c000 100"), DebugCommand::None));
+        assert!(matches!(parse("fill c000 20 00"), DebugCommand::Fill(0xC000, 0x20, 0x00)));
+        assert!(matches!(parse("find 3e 01cd"), DebugCommand::Find(p) if p == [0x3E, 0x01, 0xCD]));
+        assert!(matches!(parse("find 3e0"), DebugCommand::None));
+    }
+
+    #[test]
+    fn test_parse_reg_command() {
+        assert!(matches!(parse("reg"), DebugCommand::PrintRegisters));
+        assert!(matches!(
+            parse("reg HL c000"),
+            DebugCommand::SetRegister(Register::R16(R16::HL), 0xC000)
+        ));
+        assert!(matches!(parse("reg a 100"), DebugCommand::None));
+        assert!(matches!(parse("reg xy 1"), DebugCommand::None));
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        let symbols = Symbols::parse("00:0150 Main\n00:c000 wCounter");
+        let parse = |input: &str| parse_user_input(input.to_string(), &symbols);
+
+        assert!(matches!(parse("break Main"), DebugCommand::Break(0x0150, None)));
+        assert!(matches!(parse("b 0200"), DebugCommand::Break(0x0200, None)));
+        assert!(matches!(parse("x wCounter 1"), DebugCommand::Examine(0xC000, 0x01)));
+        assert!(matches!(parse("dis Main 4"), DebugCommand::Disassemble(Some(0x0150), 4)));
+        assert!(matches!(parse("dis"), DebugCommand::Disassemble(None, 10)));
+        assert!(matches!(parse("break Nowhere"), DebugCommand::None));
+        assert!(matches!(parse("until Main"), DebugCommand::Until(0x0150)));
+    }
+
+    #[test]
+    fn test_parse_step_commands() {
+        assert!(matches!(parse("n"), DebugCommand::Step(1)));
+        assert!(matches!(parse("stepi 20"), DebugCommand::Step(20)));
+        assert!(matches!(parse("step 0"), DebugCommand::Step(1)));
+        assert!(matches!(parse("frame"), DebugCommand::Frame));
+        assert!(matches!(parse("line"), DebugCommand::Line));
+    }
+
+    #[test]
+    fn test_parse_screenshot_commands() {
+        assert!(matches!(parse("screenshot a.png"), DebugCommand::Screenshot(_, None)));
+        assert!(matches!(parse("screenshot a.png 3"), DebugCommand::Screenshot(_, Some(3))));
+        assert!(matches!(parse("screenshot a.png 0"), DebugCommand::Invalid(_)));
+        assert!(matches!(parse("screenshot"), DebugCommand::Invalid(_)));
+    }
+
+    #[test]
+    fn test_parse_conditions() {
+        assert!(matches!(
+            parse("break 0150 if a == 3 and [ff44] > 140"),
+            DebugCommand::Break(0x0150, Some(_))
+        ));
+        assert!(matches!(parse("break 0150 when a == 3"), DebugCommand::Invalid(_)));
+        assert!(matches!(parse("break 0150 if a =="), DebugCommand::Invalid(_)));
+        assert!(matches!(
+            parse("watch c000"),
+            DebugCommand::Watch(Watchpoint { addr: 0xC000, kind: WatchKind::Write }, None)
+        ));
+        assert!(matches!(
+            parse("watch c000 rw if [c000] == 0"),
+            DebugCommand::Watch(Watchpoint { addr: 0xC000, kind: WatchKind::Access }, Some(_))
+        ));
+        assert!(matches!(parse("print (hl + 1) * 2"), DebugCommand::Print(_)));
+    }
+}
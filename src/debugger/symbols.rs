@@ -0,0 +1,145 @@
+//! Symbol files map addresses to label names. RGBDS (`rgblink -n`) and no$gmb both use the
+//! same format: one `BB:AAAA Name` entry per line, where BB is the bank and AAAA the address.
+//! Comments start with `;`, and section headers like `[labels]` are ignored.
+
+use crate::mmu::memmap::{map_bank, map_region};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    path::Path,
+};
+
+pub struct Symbols {
+    labels: BTreeMap<(u8, u16), String>,
+    addresses: HashMap<String, u16>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols {
+            labels: BTreeMap::new(),
+            addresses: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(Symbols::parse(&text))
+    }
+
+    /// Lines that don't parse are skipped rather than rejecting the whole file.
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = Symbols::new();
+
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap().trim();
+            if line.is_empty() || line.starts_with('[') {
+                continue;
+            }
+
+            let Some((location, name)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let Some((bank, addr)) = location.split_once(':') else {
+                continue;
+            };
+            let (Ok(bank), Ok(addr)) = (u8::from_str_radix(bank, 16), u16::from_str_radix(addr, 16))
+            else {
+                continue;
+            };
+
+            symbols.insert(bank, addr, name.trim());
+        }
+
+        symbols
+    }
+
+    pub fn insert(&mut self, bank: u8, addr: u16, name: &str) {
+        // The first label at an address wins, local labels usually come after their parent
+        self.labels.entry((bank, addr)).or_insert_with(|| name.to_string());
+        self.addresses.insert(name.to_string(), addr);
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn get_label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&(map_bank(addr), addr)).map(String::as_str)
+    }
+
+    pub fn get_addr(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    /// Returns the closest label at or before an address in the given bank. Labels in another
+    /// memory region don't count, e.g. HRAM isn't part of the last routine in ROM bank 0.
+    pub fn find_label(&self, bank: u8, addr: u16) -> Option<(u16, &str)> {
+        let ((label_bank, label_addr), name) = self.labels.range(..=(bank, addr)).next_back()?;
+        if *label_bank != bank || map_region(*label_addr) != map_region(addr) {
+            return None;
+        }
+        Some((*label_addr, name))
+    }
+
+    /// Describes an address relative to the closest label before it, e.g. `Main+$3`.
+    pub fn describe(&self, addr: u16) -> Option<String> {
+        let (label_addr, name) = self.find_label(map_bank(addr), addr)?;
+
+        let offset = addr - label_addr;
+        if offset == 0 {
+            Some(name.to_string())
+        } else {
+            Some(format!("{}+${:x}", name, offset))
+        }
+    }
+
+    /// Formats an address for display, with its label if there is one.
+    pub fn format_addr(&self, addr: u16) -> String {
+        match self.describe(addr) {
+            Some(description) => format!("{:04x} <{}>", addr, description),
+            None => format!("{:04x}", addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYM_FILE: &str = "; File generated by rgblink
+00:0150 Main
+00:0150 Main.loop
+00:0158 Main.done
+01:4000 Bank1Function
+02:4000 Bank2Function
+00:c000 wCounter
+[labels]
+garbage line
+";
+
+    #[test]
+    fn test_parse_sym_file() {
+        let symbols = Symbols::parse(SYM_FILE);
+        assert_eq!(symbols.len(), 6);
+        assert_eq!(symbols.get_label(0x0150), Some("Main"));
+        assert_eq!(symbols.get_label(0x4000), Some("Bank1Function"));
+        assert_eq!(symbols.get_addr("Main.done"), Some(0x0158));
+        assert_eq!(symbols.get_addr("wCounter"), Some(0xC000));
+        assert_eq!(symbols.get_addr("garbage"), None);
+    }
+
+    #[test]
+    fn test_describe_addr() {
+        let symbols = Symbols::parse(SYM_FILE);
+        assert_eq!(symbols.describe(0x0153).as_deref(), Some("Main+$3"));
+        assert_eq!(symbols.describe(0x4010).as_deref(), Some("Bank1Function+$10"));
+        assert_eq!(symbols.describe(0x0100), None);
+        // WRAM, IO and HRAM aren't part of the routines in ROM bank 0
+        assert_eq!(symbols.describe(0xC010).as_deref(), Some("wCounter+$10"));
+        assert_eq!(symbols.describe(0xD000), None);
+        assert_eq!(symbols.describe(0xFF80), None);
+        assert_eq!(symbols.format_addr(0x0158), "0158 <Main.done>");
+        assert_eq!(symbols.format_addr(0x0100), "0100");
+    }
+}
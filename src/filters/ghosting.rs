@@ -0,0 +1,86 @@
+//! DMG LCDs are slow to change, so moving things smear, and sprites that a game flickers on and
+//! off every other frame look see-through rather than flashing. Frame blending imitates this
+//! by mixing what was on the screen into each new frame.
+//!
+//! Persistence is how much of the previous output stays on the screen: 0 is none, and 0.5
+//! keeps half of the last frame, a quarter of the one before, and so on.
+
+/// Persistence to use when blending is turned on without `--ghosting`
+pub const DEFAULT_PERSISTENCE: f32 = 0.5;
+
+pub struct FrameBlender {
+    persistence: f32,
+    /// What was shown last time, kept at full precision so that it fades out smoothly
+    previous: Vec<f32>,
+}
+
+impl FrameBlender {
+    pub fn new(persistence: f32) -> Self {
+        FrameBlender {
+            persistence: persistence.clamp(0.0, 0.99),
+            previous: Vec::new(),
+        }
+    }
+
+    pub fn persistence(&self) -> f32 {
+        self.persistence
+    }
+
+    /// Forgets the previous frames, so the next one is shown as it is.
+    pub fn reset(&mut self) {
+        self.previous.clear();
+    }
+
+    /// Blends the previous frames into an RGB buffer, in place.
+    pub fn blend(&mut self, pixels: &mut [u8]) {
+        if self.previous.len() != pixels.len() {
+            self.previous = pixels.iter().map(|&value| value as f32).collect();
+            return;
+        }
+
+        for (pixel, previous) in pixels.iter_mut().zip(&mut self.previous) {
+            let current = *pixel as f32;
+            *previous = current + (*previous - current) * self.persistence;
+            *pixel = previous.round() as u8;
+        }
+    }
+}
+
+/// Parses `--ghosting`, which has to be at least 0 and less than 1.
+pub fn parse_persistence(text: &str) -> Option<f32> {
+    text.parse()
+        .ok()
+        .filter(|persistence| (0.0..1.0).contains(persistence))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blend() {
+        let mut blender = FrameBlender::new(0.5);
+
+        let mut frame = [200, 0, 100];
+        blender.blend(&mut frame);
+        assert_eq!(frame, [200, 0, 100]);
+
+        let mut frame = [0, 200, 100];
+        blender.blend(&mut frame);
+        assert_eq!(frame, [100, 100, 100]);
+
+        // Older frames fade out
+        let mut frame = [0, 200, 100];
+        blender.blend(&mut frame);
+        assert_eq!(frame, [50, 150, 100]);
+
+        blender.reset();
+        let mut frame = [10, 20, 30];
+        blender.blend(&mut frame);
+        assert_eq!(frame, [10, 20, 30]);
+
+        assert_eq!(parse_persistence("0.6"), Some(0.6));
+        assert_eq!(parse_persistence("1"), None);
+        assert_eq!(parse_persistence("-0.1"), None);
+    }
+}
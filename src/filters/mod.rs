@@ -0,0 +1,5 @@
+//! Effects applied to the screen on its way to the window. They all run on the CPU, over
+//! plain RGB buffers, so they work without a GPU.
+
+pub mod ghosting;
+pub mod upscale;
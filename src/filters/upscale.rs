@@ -0,0 +1,319 @@
+//! Pixel art upscalers, which scale the screen up by a whole number before the window scales it
+//! the rest of the way. Nearest leaves the pixels as they are, the others smooth diagonal
+//! edges without blurring, or draw the gaps between an LCD's dots.
+//!
+//! `--filter <name>` picks one, and F7 cycles through them while running.
+
+use crate::image::RgbImage;
+
+type Rgb = [u8; 3];
+/// Brightness and the two color differences, weighted by how much each is noticed
+type Yuv = [i32; 3];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Upscaler {
+    Nearest,
+    /// AdvMAME2x, which fills in the corners of each pixel from matching neighbors
+    Scale2x,
+    Scale3x,
+    /// 2xBR, which finds edges from the colors around each corner and blends along them
+    Xbr,
+    /// Each pixel becomes a 3x3 dot, with darker gaps to its right and below it
+    LcdGrid,
+}
+
+impl Upscaler {
+    pub const ALL: [Upscaler; 5] = [
+        Upscaler::Nearest,
+        Upscaler::Scale2x,
+        Upscaler::Scale3x,
+        Upscaler::Xbr,
+        Upscaler::LcdGrid,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Upscaler::Nearest => "nearest",
+            Upscaler::Scale2x => "scale2x",
+            Upscaler::Scale3x => "scale3x",
+            Upscaler::Xbr => "xbr",
+            Upscaler::LcdGrid => "lcd",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Upscaler> {
+        Upscaler::ALL
+            .into_iter()
+            .find(|upscaler| upscaler.name() == name.to_lowercase())
+    }
+
+    /// All of their names, for error messages
+    pub fn names() -> String {
+        Upscaler::ALL.map(Upscaler::name).join(", ")
+    }
+
+    /// The one after this, going back to the first after the last one.
+    pub fn next(self) -> Upscaler {
+        let index = Upscaler::ALL.iter().position(|&upscaler| upscaler == self);
+        Upscaler::ALL[(index.unwrap() + 1) % Upscaler::ALL.len()]
+    }
+
+    pub fn factor(self) -> usize {
+        match self {
+            Upscaler::Nearest => 1,
+            Upscaler::Scale2x | Upscaler::Xbr => 2,
+            Upscaler::Scale3x | Upscaler::LcdGrid => 3,
+        }
+    }
+
+    /// Returns the image scaled up by `factor()`.
+    pub fn upscale(self, image: &RgbImage) -> RgbImage {
+        match self {
+            Upscaler::Nearest => RgbImage {
+                width: image.width,
+                height: image.height,
+                pixels: image.pixels.clone(),
+            },
+            Upscaler::Scale2x => upscale_blocks(image, 2, |rgb| rgb, scale2x),
+            Upscaler::Scale3x => upscale_blocks(image, 3, |rgb| rgb, scale3x),
+            // Every color is compared many times over, so it's converted once up front
+            Upscaler::Xbr => upscale_blocks(image, 2, |rgb| (rgb, to_yuv(rgb)), xbr),
+            Upscaler::LcdGrid => upscale_blocks(image, 3, |rgb| rgb, lcd_grid),
+        }
+    }
+}
+
+/// Each source pixel becomes a block of `factor` x `factor` pixels, which `block` returns row
+/// by row. It looks at the pixels around it as whatever `convert` makes of their colors.
+fn upscale_blocks<T: Copy, const PIXELS: usize>(
+    image: &RgbImage,
+    factor: usize,
+    convert: fn(Rgb) -> T,
+    block: fn(&Neighbors<T>) -> [Rgb; PIXELS],
+) -> RgbImage {
+    let colors = pad(image, convert);
+    let stride = image.width + 2 * PADDING;
+    let mut output = RgbImage::new(image.width * factor, image.height * factor);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let index = (y + PADDING) * stride + x + PADDING;
+            let pixels = block(&Neighbors {
+                colors: &colors,
+                stride,
+                index,
+            });
+            for (index, rgb) in pixels.into_iter().enumerate() {
+                output.set(
+                    x * factor + index % factor,
+                    y * factor + index / factor,
+                    rgb,
+                );
+            }
+        }
+    }
+    output
+}
+
+/// How far the upscalers look from each pixel
+const PADDING: usize = 2;
+
+/// The image's colors, with its edges repeated outwards by `PADDING` pixels, so that the
+/// neighbors of every pixel are there to look at.
+fn pad<T>(image: &RgbImage, convert: fn(Rgb) -> T) -> Vec<T> {
+    let (width, height) = (image.width + 2 * PADDING, image.height + 2 * PADDING);
+    let mut colors = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let y = y.saturating_sub(PADDING).min(image.height - 1);
+        for x in 0..width {
+            let x = x.saturating_sub(PADDING).min(image.width - 1);
+            colors.push(convert(image.get(x, y)));
+        }
+    }
+    colors
+}
+
+/// The pixels around one
+struct Neighbors<'a, T> {
+    colors: &'a [T],
+    stride: usize,
+    index: usize,
+}
+
+impl<T: Copy> Neighbors<'_, T> {
+    fn get(&self, dx: i32, dy: i32) -> T {
+        let offset = dy as isize * self.stride as isize + dx as isize;
+        self.colors[self.index.wrapping_add_signed(offset)]
+    }
+}
+
+fn scale2x(n: &Neighbors<Rgb>) -> [Rgb; 4] {
+    let (b, d, e, f, h) = (
+        n.get(0, -1),
+        n.get(-1, 0),
+        n.get(0, 0),
+        n.get(1, 0),
+        n.get(0, 1),
+    );
+    if b == h || d == f {
+        return [e; 4];
+    }
+    let pick = |condition: bool, color: Rgb| if condition { color } else { e };
+    [
+        pick(d == b, d),
+        pick(b == f, f),
+        pick(d == h, d),
+        pick(h == f, f),
+    ]
+}
+
+fn scale3x(n: &Neighbors<Rgb>) -> [Rgb; 9] {
+    let [a, b, c] = [n.get(-1, -1), n.get(0, -1), n.get(1, -1)];
+    let [d, e, f] = [n.get(-1, 0), n.get(0, 0), n.get(1, 0)];
+    let [g, h, i] = [n.get(-1, 1), n.get(0, 1), n.get(1, 1)];
+    if b == h || d == f {
+        return [e; 9];
+    }
+    let pick = |condition: bool, color: Rgb| if condition { color } else { e };
+    [
+        pick(d == b, d),
+        pick((d == b && e != c) || (b == f && e != a), b),
+        pick(b == f, f),
+        pick((d == b && e != g) || (d == h && e != a), d),
+        e,
+        pick((b == f && e != i) || (h == f && e != c), f),
+        pick(d == h, d),
+        pick((d == h && e != i) || (h == f && e != g), h),
+        pick(h == f, f),
+    ]
+}
+
+/// Each corner is worked out the same way, with the neighborhood mirrored so that the corner
+/// is always the bottom right one.
+fn xbr(n: &Neighbors<(Rgb, Yuv)>) -> [Rgb; 4] {
+    [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+        .map(|(mirror_x, mirror_y)| xbr_corner(|dx, dy| n.get(dx * mirror_x, dy * mirror_y)))
+}
+
+/// The bottom right corner of E, from around it:
+/// ```text
+///        B
+///     D  E  F  F4
+///     G  H  I  I4
+///        H5 I5
+/// ```
+/// If the colors change less along H-F than across it, there's an edge there, so the corner
+/// is blended with whichever of H or F is closer to E.
+fn xbr_corner(get: impl Fn(i32, i32) -> (Rgb, Yuv)) -> Rgb {
+    let (e, f, h) = (get(0, 0), get(1, 0), get(0, 1));
+    // No edge can run between H and F if E is the same as either, which is most of the screen
+    if e.0 == f.0 || e.0 == h.0 {
+        return e.0;
+    }
+
+    let (b, d) = (get(0, -1), get(-1, 0));
+    let (c, g, i) = (get(1, -1), get(-1, 1), get(1, 1));
+    let (f4, i4, h5, i5) = (get(2, 0), get(2, 1), get(0, 2), get(1, 2));
+
+    let along =
+        distance(e, c) + distance(e, g) + distance(i, f4) + distance(i, h5) + 4 * distance(h, f);
+    let across =
+        distance(h, d) + distance(h, i5) + distance(f, i4) + distance(f, b) + 4 * distance(e, i);
+    if along >= across {
+        return e.0;
+    }
+
+    let edge = if distance(e, f) <= distance(e, h) {
+        f
+    } else {
+        h
+    };
+    mix(e.0, edge.0)
+}
+
+fn lcd_grid(n: &Neighbors<Rgb>) -> [Rgb; 9] {
+    let dot = n.get(0, 0);
+    let gap = dot.map(|value| (value as u16 * 3 / 4) as u8);
+    [dot, dot, gap, dot, dot, gap, gap, gap, gap]
+}
+
+/// Brightness counts for the most, the way eyes see it. The weights are in thousandths, to
+/// keep it in integers.
+fn to_yuv([r, g, b]: Rgb) -> Yuv {
+    let [r, g, b] = [r as i32, g as i32, b as i32];
+    [
+        48 * (299 * r + 587 * g + 114 * b),
+        7 * (-169 * r - 331 * g + 500 * b),
+        6 * (500 * r - 419 * g - 81 * b),
+    ]
+}
+
+/// How different two colors look
+fn distance((_, a): (Rgb, Yuv), (_, b): (Rgb, Yuv)) -> u32 {
+    let difference = |channel: usize| a[channel].abs_diff(b[channel]);
+    (difference(0) + difference(1) + difference(2)) / 1000
+}
+
+fn mix(a: Rgb, b: Rgb) -> Rgb {
+    [0, 1, 2].map(|channel| ((a[channel] as u16 + b[channel] as u16) / 2) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Rgb = [0, 0, 0];
+    const WHITE: Rgb = [255, 255, 255];
+
+    /// A black diagonal line on white, from the top left
+    fn diagonal() -> RgbImage {
+        let mut image = RgbImage::new(4, 4);
+        for y in 0..4 {
+            for x in 0..4 {
+                image.set(x, y, if x == y { BLACK } else { WHITE });
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_upscalers() {
+        let image = diagonal();
+        for upscaler in Upscaler::ALL {
+            let output = upscaler.upscale(&image);
+            assert_eq!(output.width, 4 * upscaler.factor());
+            assert_eq!(output.height, 4 * upscaler.factor());
+            // The middle of each pixel stays the same
+            let middle = upscaler.factor() / 2;
+            let pixel = |x: usize, y: usize| output.get(x + middle, y + middle);
+            assert_eq!(pixel(2 * upscaler.factor(), 2 * upscaler.factor()), BLACK);
+            assert_eq!(pixel(2 * upscaler.factor(), upscaler.factor()), WHITE);
+        }
+
+        // Scale2x fills in the steps of the diagonal, so it's two pixels thick on each side
+        let output = Upscaler::Scale2x.upscale(&image);
+        assert_eq!(output.get(3, 2), BLACK);
+        assert_eq!(output.get(2, 3), BLACK);
+        assert_eq!(output.get(3, 0), WHITE);
+
+        let output = Upscaler::Scale3x.upscale(&image);
+        assert_eq!(output.get(4, 3), BLACK);
+        assert_eq!(output.get(3, 4), BLACK);
+
+        // 2xBR blends the corners along the line
+        let output = Upscaler::Xbr.upscale(&image);
+        assert_eq!(output.get(3, 2), [127, 127, 127]);
+
+        // The dots are separated by darker gaps
+        let output = Upscaler::LcdGrid.upscale(&image);
+        assert_eq!(output.get(4, 0), WHITE);
+        assert_eq!(output.get(5, 0), [191, 191, 191]);
+    }
+
+    #[test]
+    fn test_parse_and_cycle() {
+        assert_eq!(Upscaler::parse("Scale2x"), Some(Upscaler::Scale2x));
+        assert_eq!(Upscaler::parse("hq9x"), None);
+        assert_eq!(Upscaler::Nearest.next(), Upscaler::Scale2x);
+        assert_eq!(Upscaler::LcdGrid.next(), Upscaler::Nearest);
+    }
+}
@@ -0,0 +1,106 @@
+//! PNG encoding and decoding for anything that needs to get pixels in or out of the emulator.
+//! Images are passed around as tightly packed 8-bit RGB buffers, row by row.
+
+use std::{fs::File, io::BufWriter, path::Path};
+
+pub const BYTES_PER_PIXEL: usize = 3;
+
+pub struct RgbImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbImage {
+    pub fn new(width: usize, height: usize) -> Self {
+        RgbImage {
+            width,
+            height,
+            pixels: vec![0; width * height * BYTES_PER_PIXEL],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [u8; 3] {
+        let index = (y * self.width + x) * BYTES_PER_PIXEL;
+        [
+            self.pixels[index],
+            self.pixels[index + 1],
+            self.pixels[index + 2],
+        ]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        let index = (y * self.width + x) * BYTES_PER_PIXEL;
+        self.pixels[index..index + BYTES_PER_PIXEL].copy_from_slice(&rgb);
+    }
+}
+
+pub fn save_png(path: &Path, image: &RgbImage) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        image.width as u32,
+        image.height as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.pixels)?;
+    Ok(())
+}
+
+/// Loads any 8-bit or 16-bit PNG, converting it to RGB. Alpha is discarded.
+#[cfg(test)]
+pub fn load_png(path: &Path) -> std::io::Result<RgbImage> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    let width = info.width as usize;
+    let height = info.height as usize;
+
+    let channels = info.color_type.samples();
+    let mut image = RgbImage::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y * width + x) * channels;
+            let rgb = match info.color_type {
+                png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => {
+                    [buf[index], buf[index], buf[index]]
+                }
+                _ => [buf[index], buf[index + 1], buf[index + 2]],
+            };
+            image.set(x, y, rgb);
+        }
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_png_round_trip() {
+        let mut image = RgbImage::new(3, 2);
+        image.set(0, 0, [0xFF, 0x00, 0x00]);
+        image.set(2, 1, [0x12, 0x34, 0x56]);
+
+        let path = std::env::temp_dir().join("gameboy-emulator-png-round-trip.png");
+        save_png(&path, &image).unwrap();
+        let loaded = load_png(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.width, 3);
+        assert_eq!(loaded.height, 2);
+        assert_eq!(loaded.pixels, image.pixels);
+    }
+}
@@ -0,0 +1,388 @@
+//! A link cable between two emulators, over TCP. One end listens (`--link-host`) and the other
+//! connects to it (`--link-connect`).
+//!
+//! When a Game Boy starts a transfer with its internal clock, it sends its byte and blocks until
+//! the other end replies with its own. The other end answers from its serial device tick, so the
+//! byte it replies with is whatever was in SB at that moment.
+//!
+//! By default, the other end checks for transfers every few hundred t-cycles, whenever it gets
+//! to them. With `--link-lockstep`, both ends stop to sync every `LOCKSTEP_T_CYCLES`, and only
+//! answer transfers at those syncs. Neither end can get ahead of the other by more than that,
+//! and a transfer is always answered at the same point in emulated time, so a session plays out
+//! the same way every time.
+//! While a Game Boy is stopped, its serial device isn't ticked, so a lockstepped partner waits.
+//!
+//! Two Game Boys in the same process don't need any of that. They are wired together with a
+//! pair of `LinkPort`s, and stay in step as long as they are ticked together.
+
+use crate::cli::Options;
+use crate::logging::SERIAL;
+use crate::mmu::serial::{DISCONNECTED_BYTE, SerialDevice};
+use log::debug;
+use std::cell::RefCell;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+
+/// How often the ends sync in lockstep mode
+const LOCKSTEP_T_CYCLES: u32 = 4096;
+/// How often to check for transfers from the other end, otherwise
+const POLL_T_CYCLES: u32 = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Message {
+    /// Sent on connecting, so that both ends agree on the mode
+    Hello { lockstep: bool },
+    /// The sender started a transfer with its internal clock
+    Transfer(u8),
+    /// The answer to a transfer
+    Reply(u8),
+    /// The sender has finished a lockstep slice
+    Sync,
+}
+
+impl Message {
+    fn encode(self) -> [u8; 2] {
+        match self {
+            Message::Hello { lockstep } => [0, lockstep as u8],
+            Message::Transfer(byte) => [1, byte],
+            Message::Reply(byte) => [2, byte],
+            Message::Sync => [3, 0],
+        }
+    }
+
+    fn decode(bytes: [u8; 2]) -> Option<Message> {
+        match bytes {
+            [0, lockstep] => Some(Message::Hello {
+                lockstep: lockstep != 0,
+            }),
+            [1, byte] => Some(Message::Transfer(byte)),
+            [2, byte] => Some(Message::Reply(byte)),
+            [3, _] => Some(Message::Sync),
+            _ => None,
+        }
+    }
+}
+
+pub struct LinkCable {
+    stream: TcpStream,
+    nonblocking: bool,
+    /// Bytes that have been read, but don't make up a whole message yet
+    buffer: Vec<u8>,
+    lockstep: bool,
+    t_cycles: u32,
+    /// Syncs the other end has sent, that this end hasn't reached yet
+    peer_syncs: u32,
+    connected: bool,
+}
+
+impl LinkCable {
+    /// Sets up a link cable from the options, if there is one. Blocks until the other end is
+    /// connected.
+    pub fn from_options(options: &Options) -> Option<LinkCable> {
+        let result = if let Some(addr) = &options.link_host {
+            // Just a port listens on localhost
+            let addr = match addr.contains(':') {
+                true => addr.clone(),
+                false => format!("127.0.0.1:{}", addr),
+            };
+            TcpListener::bind(&addr).and_then(|listener| {
+                println!("Waiting for the other end of the link cable on {}", addr);
+                LinkCable::accept(&listener, options.link_lockstep)
+            })
+        } else if let Some(addr) = &options.link_connect {
+            println!("Connecting the link cable to {}", addr);
+            TcpStream::connect(addr)
+                .and_then(|stream| LinkCable::new(stream, options.link_lockstep))
+        } else {
+            return None;
+        };
+
+        match result {
+            Ok(cable) => {
+                println!("Link cable connected");
+                Some(cable)
+            }
+            Err(error) => {
+                println!("Failed to connect the link cable: {}", error);
+                None
+            }
+        }
+    }
+
+    pub fn accept(listener: &TcpListener, lockstep: bool) -> io::Result<LinkCable> {
+        let (stream, _) = listener.accept()?;
+        LinkCable::new(stream, lockstep)
+    }
+
+    pub fn new(stream: TcpStream, lockstep: bool) -> io::Result<LinkCable> {
+        stream.set_nodelay(true)?;
+        let mut cable = LinkCable {
+            stream,
+            nonblocking: false,
+            buffer: Vec::new(),
+            lockstep,
+            t_cycles: 0,
+            peer_syncs: 0,
+            connected: true,
+        };
+
+        cable.send(Message::Hello { lockstep })?;
+        match cable.read_message(true)? {
+            Some(Message::Hello { lockstep: peer }) if peer == lockstep => Ok(cable),
+            Some(Message::Hello { .. }) => Err(io::Error::other(
+                "both ends must use --link-lockstep, or neither",
+            )),
+            _ => Err(io::Error::other("the other end isn't a link cable")),
+        }
+    }
+
+    fn send(&mut self, message: Message) -> io::Result<()> {
+        self.stream.write_all(&message.encode())
+    }
+
+    /// Without blocking, returns None if no whole message has arrived yet.
+    fn read_message(&mut self, blocking: bool) -> io::Result<Option<Message>> {
+        loop {
+            if self.buffer.len() >= 2 {
+                let bytes = [self.buffer[0], self.buffer[1]];
+                self.buffer.drain(..2);
+                match Message::decode(bytes) {
+                    Some(message) => return Ok(Some(message)),
+                    None => return Err(io::Error::other("received an invalid message")),
+                }
+            }
+
+            if self.nonblocking == blocking {
+                self.stream.set_nonblocking(!blocking)?;
+                self.nonblocking = !blocking;
+            }
+
+            let mut chunk = [0; 64];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(count) => self.buffer.extend_from_slice(&chunk[..count]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Answers a transfer from the other end. Returns the byte it sent, if this end was waiting
+    /// for it.
+    fn answer_transfer(&mut self, byte: u8, sb: u8, waiting: bool) -> io::Result<Option<u8>> {
+        debug!(target: SERIAL, "Link: received {:02x}, replied {:02x}", byte, sb);
+        self.send(Message::Reply(sb))?;
+        Ok(waiting.then_some(byte))
+    }
+
+    /// Sends this end's sync, and waits for the other end to reach the same point, answering
+    /// the transfers it started on the way.
+    fn sync(&mut self, sb: u8, waiting: bool) -> io::Result<Option<u8>> {
+        self.send(Message::Sync)?;
+
+        let mut received = None;
+        while self.peer_syncs == 0 {
+            match self.read_message(true)? {
+                Some(Message::Sync) => self.peer_syncs += 1,
+                Some(Message::Transfer(byte)) => {
+                    received = self.answer_transfer(byte, sb, waiting)?.or(received);
+                }
+                _ => {}
+            }
+        }
+        self.peer_syncs -= 1;
+        Ok(received)
+    }
+
+    /// Answers any transfers that have arrived, without waiting for more.
+    fn poll(&mut self, sb: u8, waiting: bool) -> io::Result<Option<u8>> {
+        let mut received = None;
+        while let Some(message) = self.read_message(false)? {
+            if let Message::Transfer(byte) = message {
+                received = self.answer_transfer(byte, sb, waiting)?.or(received);
+            }
+        }
+        Ok(received)
+    }
+
+    fn exchange(&mut self, byte: u8) -> io::Result<u8> {
+        self.send(Message::Transfer(byte))?;
+        loop {
+            match self.read_message(true)? {
+                Some(Message::Reply(reply)) => return Ok(reply),
+                // Both ends drove the clock at once. Answering keeps either from getting stuck.
+                Some(Message::Transfer(_)) => self.send(Message::Reply(byte))?,
+                Some(Message::Sync) => self.peer_syncs += 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// From then on, the cable acts as if nothing is plugged in.
+    fn disconnect(&mut self, error: io::Error) {
+        println!("Link cable disconnected: {}", error);
+        self.connected = false;
+    }
+}
+
+impl SerialDevice for LinkCable {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        if !self.connected {
+            return DISCONNECTED_BYTE;
+        }
+        match self.exchange(byte) {
+            Ok(reply) => reply,
+            Err(error) => {
+                self.disconnect(error);
+                DISCONNECTED_BYTE
+            }
+        }
+    }
+
+    fn tick(&mut self, sb: u8, waiting: bool) -> Option<u8> {
+        if !self.connected {
+            return None;
+        }
+
+        self.t_cycles += 1;
+        let period = if self.lockstep {
+            LOCKSTEP_T_CYCLES
+        } else {
+            POLL_T_CYCLES
+        };
+        if self.t_cycles < period {
+            return None;
+        }
+        self.t_cycles = 0;
+
+        let result = if self.lockstep {
+            self.sync(sb, waiting)
+        } else {
+            self.poll(sb, waiting)
+        };
+        result.unwrap_or_else(|error| {
+            self.disconnect(error);
+            None
+        })
+    }
+}
+
+/// What each end of an in-process cable last put on it
+#[derive(Clone, Copy)]
+struct WireEnd {
+    sb: u8,
+    /// A byte the other end sent with its internal clock, that hasn't been picked up yet
+    incoming: Option<u8>,
+}
+
+/// One end of a cable between two Game Boys in the same process.
+/// A transfer is answered with the other end's SB as of its last tick, and the byte sent is
+/// picked up on the other end's next tick, so ticking the Game Boys in turn keeps it exact.
+pub struct LinkPort {
+    wire: Rc<RefCell<[WireEnd; 2]>>,
+    end: usize,
+}
+
+/// Returns both ends of a new cable.
+pub fn cross_wired_ports() -> (LinkPort, LinkPort) {
+    let end = WireEnd {
+        sb: DISCONNECTED_BYTE,
+        incoming: None,
+    };
+    let wire = Rc::new(RefCell::new([end; 2]));
+    (
+        LinkPort {
+            wire: Rc::clone(&wire),
+            end: 0,
+        },
+        LinkPort { wire, end: 1 },
+    )
+}
+
+impl SerialDevice for LinkPort {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut wire = self.wire.borrow_mut();
+        let other_end = &mut wire[1 - self.end];
+        other_end.incoming = Some(byte);
+        other_end.sb
+    }
+
+    fn tick(&mut self, sb: u8, waiting: bool) -> Option<u8> {
+        let mut wire = self.wire.borrow_mut();
+        let this_end = &mut wire[self.end];
+        this_end.sb = sb;
+        // If this end wasn't waiting for it, the byte is lost, as it would be on hardware
+        this_end.incoming.take().filter(|_| waiting)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmu::{Mmu, memmap::*};
+    use crate::util::get_bit;
+
+    /// Long enough for a transfer to be answered at the first sync, and then shifted in
+    const T_CYCLES: u32 = 3 * LOCKSTEP_T_CYCLES;
+
+    /// Runs one end of the cable, then returns its SB and whether it got a serial interrupt.
+    fn run_end(cable: LinkCable, sb: u8, sc: u8) -> (u8, bool) {
+        let mmu = Mmu::new();
+        let mut mmu = mmu.borrow_mut();
+        mmu.set_serial_device(Box::new(cable));
+
+        mmu.write_byte(SB_ADDR, sb);
+        mmu.write_byte(SC_ADDR, sc);
+        for _ in 0..T_CYCLES {
+            mmu.tick_timers();
+            mmu.tick_serial();
+        }
+
+        let interrupt = get_bit(mmu.read_byte_override(IF_ADDR), SERIAL_INTERRUPT_BIT);
+        (mmu.read_byte(SB_ADDR), interrupt)
+    }
+
+    #[test]
+    fn test_lockstep_transfer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Each end has its own Game Boy, which can't leave the thread it was made on
+        let other_end = std::thread::spawn(move || {
+            let cable = LinkCable::new(TcpStream::connect(addr).unwrap(), true).unwrap();
+            run_end(cable, 0x99, 0x80)
+        });
+        let cable = LinkCable::accept(&listener, true).unwrap();
+        let this_end = run_end(cable, 0x42, 0x81);
+
+        assert_eq!(this_end, (0x99, true));
+        assert_eq!(other_end.join().unwrap(), (0x42, true));
+    }
+
+    #[test]
+    fn test_cross_wired_ports() {
+        let (port_0, port_1) = cross_wired_ports();
+        let (mmu_0, mmu_1) = (Mmu::new(), Mmu::new());
+        mmu_0.borrow_mut().set_serial_device(Box::new(port_0));
+        mmu_1.borrow_mut().set_serial_device(Box::new(port_1));
+
+        mmu_1.borrow_mut().write_byte(SB_ADDR, 0x99);
+        mmu_1.borrow_mut().write_byte(SC_ADDR, 0x80);
+        // The other end has to tick once, for its SB to be on the wire
+        mmu_1.borrow_mut().tick_serial();
+        mmu_0.borrow_mut().write_byte(SB_ADDR, 0x42);
+        mmu_0.borrow_mut().write_byte(SC_ADDR, 0x81);
+
+        for _ in 0..9 * 512 {
+            for mmu in [&mmu_0, &mmu_1] {
+                mmu.borrow_mut().tick_timers();
+                mmu.borrow_mut().tick_serial();
+            }
+        }
+        assert_eq!(mmu_0.borrow().read_byte(SB_ADDR), 0x99);
+        assert_eq!(mmu_1.borrow().read_byte(SB_ADDR), 0x42);
+        assert_eq!(mmu_1.borrow().read_byte(SC_ADDR), 0x7E);
+    }
+}
@@ -0,0 +1,160 @@
+//! Two Game Boys in one process, with a link cable between their serial ports.
+//! They are ticked in turn, one t-cycle at a time, so a linked session always plays out the
+//! same way. That makes it a harness for testing link cable games, as well as a way to play
+//! them without a second emulator.
+//!
+//! Both screens are shown side by side. The controls go to one Game Boy at a time, and Tab
+//! switches between them.
+
+use crate::cli::Options;
+use crate::cpu::Cpu;
+use crate::link::cross_wired_ports;
+use crate::mmu::Mmu;
+use crate::ppu::Ppu;
+use crate::ui::{Inputs, UserInterface};
+use crate::{
+    create_gameboy_components, emulate_boot, process_inputs, set_buttons, tick_gameboy,
+    update_display,
+};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+pub struct GameBoy {
+    pub mmu: Rc<RefCell<Mmu>>,
+    pub cpu: Cpu,
+    pub ppu: Ppu,
+}
+
+impl GameBoy {
+    fn new() -> Self {
+        let (mmu, cpu, ppu) = create_gameboy_components();
+        GameBoy { mmu, cpu, ppu }
+    }
+
+    fn tick(&mut self) {
+        tick_gameboy(&mut self.cpu, &self.mmu, &mut self.ppu);
+    }
+}
+
+pub struct LinkedGameBoys {
+    pub game_boys: [GameBoy; 2],
+}
+
+impl LinkedGameBoys {
+    pub fn new() -> Self {
+        let game_boys = [GameBoy::new(), GameBoy::new()];
+        let (port_0, port_1) = cross_wired_ports();
+        game_boys[0]
+            .mmu
+            .borrow_mut()
+            .set_serial_device(Box::new(port_0));
+        game_boys[1]
+            .mmu
+            .borrow_mut()
+            .set_serial_device(Box::new(port_1));
+        LinkedGameBoys { game_boys }
+    }
+
+    /// Returns false if either ROM fails to load.
+    pub fn load_roms(&mut self, paths: &[String; 2]) -> bool {
+        for (game_boy, path) in self.game_boys.iter_mut().zip(paths) {
+            if !game_boy.mmu.borrow_mut().load_rom(path) {
+                println!("Failed to load rom at \"{}\"", path);
+                return false;
+            }
+            emulate_boot(&game_boy.mmu, &mut game_boy.cpu);
+        }
+        true
+    }
+
+    /// Progresses both Game Boys by one t-cycle.
+    pub fn tick(&mut self) {
+        for game_boy in &mut self.game_boys {
+            game_boy.tick();
+        }
+    }
+}
+
+pub fn run_linked(paths: &[String; 2], options: &Options) {
+    println!("\nLoading roms at: \"{}\" and \"{}\"", paths[0], paths[1]);
+
+    let mut linked = LinkedGameBoys::new();
+    if !linked.load_roms(paths) {
+        return;
+    }
+
+    let mut ui = UserInterface::with_screens(2, options);
+    // Which Game Boy the controls go to
+    let mut focus = 0;
+
+    let render_timer_period = Duration::from_secs_f64(1.0 / 60.0);
+    let mut last_render_time = Instant::now();
+
+    while ui.running {
+        linked.tick();
+        for (screen, game_boy) in linked.game_boys.iter_mut().enumerate() {
+            if game_boy.ppu.take_frame_ready() {
+                update_display(&game_boy.cpu, &mut game_boy.ppu);
+                ui.draw_frame(screen, &game_boy.ppu.get_frame());
+            }
+        }
+
+        if last_render_time.elapsed() >= render_timer_period {
+            process_inputs(&mut ui, &linked.game_boys[focus].mmu);
+            if ui.inputs_unique.tab {
+                // Let go of everything on the one that's losing the controls
+                set_buttons(&linked.game_boys[focus].mmu, Inputs::new());
+                focus = 1 - focus;
+                println!("Controls switched to Game Boy {}", focus + 1);
+            }
+
+            ui.present();
+
+            last_render_time = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::registers::R16;
+    use crate::mmu::memmap::*;
+
+    /// Starts a transfer with the given clock, then spins until it finishes
+    fn load_transfer(game_boy: &mut GameBoy, sb: u8, sc: u8) {
+        // LD A,sb; LDH (SB),A; LD A,sc; LDH (SC),A; loop: LDH A,(SC); BIT 7,A; JR NZ,loop; STOP
+        let program = [
+            0x3E, sb, 0xE0, 0x01, 0x3E, sc, 0xE0, 0x02, 0xF0, 0x02, 0xCB, 0x7F, 0x20, 0xFA, 0x10,
+        ];
+        let mut mmu = game_boy.mmu.borrow_mut();
+        for (i, byte) in program.iter().enumerate() {
+            mmu.write_byte_override(0xC000 + i as u16, *byte);
+        }
+        game_boy.cpu.reg.set16(R16::PC, 0xC000);
+    }
+
+    #[test]
+    fn test_linked_transfer() {
+        let mut linked = LinkedGameBoys::new();
+        let [master, slave] = &mut linked.game_boys;
+        load_transfer(slave, 0x99, 0x80);
+        load_transfer(master, 0x42, 0x81);
+
+        // Give the slave time to get ready before the master starts
+        for _ in 0..200 {
+            slave.tick();
+        }
+        for _ in 0..10 * 512 {
+            linked.tick();
+        }
+
+        for (game_boy, received) in linked.game_boys.iter().zip([0x99, 0x42]) {
+            assert!(game_boy.cpu.is_stopped());
+            assert_eq!(game_boy.mmu.borrow().read_byte_override(SB_ADDR), received);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Logging goes through the `log` facade. Each component logs to its own target (category),
+//! and every category has its own level, so that e.g. interrupts can be traced without
+//! drowning in per-instruction CPU output.
+//!
+//! Levels are set with a spec string, either from the CLI (`--log`) or the debugger (`log`).
+//! A spec is a comma separated list of `category=level` pairs, where a bare level applies to
+//! every category. For example: `warn,interrupts=debug,ppu=trace`.
+
+use log::{LevelFilter, Log, Metadata, Record};
+use std::{str::FromStr, sync::RwLock};
+
+pub const CPU: &str = "cpu";
+pub const INTERRUPTS: &str = "interrupts";
+pub const PPU: &str = "ppu";
+pub const DMA: &str = "dma";
+pub const TIMERS: &str = "timers";
+pub const SERIAL: &str = "serial";
+
+pub const CATEGORIES: [&str; 6] = [CPU, INTERRUPTS, PPU, DMA, TIMERS, SERIAL];
+
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Warn;
+
+static LEVELS: RwLock<[LevelFilter; CATEGORIES.len()]> =
+    RwLock::new([DEFAULT_LEVEL; CATEGORIES.len()]);
+static LOGGER: Logger = Logger;
+
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= get_level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{:<5} {}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger. Every category starts out at the default level.
+pub fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        update_max_level();
+    }
+}
+
+/// Returns false if the spec is malformed, in which case no levels are changed.
+pub fn set_levels(spec: &str) -> bool {
+    let mut changes = Vec::new();
+
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (category, level) = match entry.split_once('=') {
+            Some((category, level)) => (Some(category.trim()), level.trim()),
+            None => (None, entry),
+        };
+
+        let Ok(level) = LevelFilter::from_str(level) else {
+            return false;
+        };
+
+        match category {
+            None | Some("all") => changes.extend(CATEGORIES.iter().map(|&c| (c, level))),
+            Some(category) => match CATEGORIES.iter().find(|&&c| c == category) {
+                Some(&category) => changes.push((category, level)),
+                None => return false,
+            },
+        }
+    }
+
+    let mut levels = LEVELS.write().unwrap();
+    for (category, level) in changes {
+        levels[category_index(category).unwrap()] = level;
+    }
+    drop(levels);
+
+    update_max_level();
+    true
+}
+
+/// Targets that aren't one of the categories (dependencies, for example) use the default level.
+pub fn get_level(target: &str) -> LevelFilter {
+    match category_index(target) {
+        Some(index) => LEVELS.read().unwrap()[index],
+        None => DEFAULT_LEVEL,
+    }
+}
+
+pub fn print_levels() {
+    println!("\nLog levels:");
+    for category in CATEGORIES {
+        println!("{:<12}{}", category, get_level(category));
+    }
+    println!();
+}
+
+fn category_index(target: &str) -> Option<usize> {
+    CATEGORIES.iter().position(|&category| category == target)
+}
+
+/// The `log` macros check the global max level before anything else, so keeping it as low
+/// as possible makes disabled logging (nearly) free in the hot emulation loop.
+fn update_max_level() {
+    let levels = LEVELS.read().unwrap();
+    let max_level = levels.iter().copied().fold(DEFAULT_LEVEL, Ord::max);
+    log::set_max_level(max_level);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_levels() {
+        assert!(set_levels("error,ppu=trace, dma = debug"));
+        assert_eq!(get_level(CPU), LevelFilter::Error);
+        assert_eq!(get_level(PPU), LevelFilter::Trace);
+        assert_eq!(get_level(DMA), LevelFilter::Debug);
+
+        // Malformed specs don't change anything
+        assert!(!set_levels("cpu=trace,gpu=debug"));
+        assert!(!set_levels("cpu=loud"));
+        assert_eq!(get_level(CPU), LevelFilter::Error);
+
+        assert!(set_levels("all=warn"));
+        assert_eq!(get_level(PPU), LevelFilter::Warn);
+    }
+}
@@ -3,39 +3,74 @@
 
 mod cli;
 mod debugger;
+mod filters;
 mod constants;
 mod cpu;
+mod image;
+mod link;
+mod linked;
+mod logging;
 mod mmu;
+mod palette;
 mod ppu;
+mod printer;
+mod profiler;
+mod recorder;
+mod screenshot;
+#[cfg(test)]
+mod regression;
 mod ui;
 mod util;
 
-use cli::{Command, parse_cli_inputs};
+use cli::{Command, Options, parse_cli_inputs};
 
 use cpu::{registers::R8, Cpu};
-use debugger::run_debug;
-use mmu::{Mmu, memmap::*};
+use debugger::{run_debug, run_gdb_server, symbols::Symbols};
+use link::LinkCable;
+use linked::run_linked;
+use mmu::{Mmu, joypad::Button, memmap::*};
 use ppu::Ppu;
+use printer::Printer;
+use profiler::Profiler;
+use recorder::{RECORDING_DIR, Recorder, start_recording, stop_recording};
 use std::{
     cell::RefCell,
+    path::Path,
     rc::Rc,
     time::{Duration, Instant},
 };
-use ui::UserInterface;
+use ui::{Inputs, UserInterface};
 use cpu::registers::R16;
 
 const SYSTEM_CLOCK_FREQUENCY: f64 = (1 << 22) as f64; // Hz
 const SYSTEM_CLOCK_PERIOD: f64 = 1.0 / SYSTEM_CLOCK_FREQUENCY; // Seconds
 
 fn main() {
-    let input = parse_cli_inputs();
-    match input {
-        Command::Rom(path) => run_rom(&path),
-        Command::Debug(path) => run_debug(&path),
+    let (command, options) = parse_cli_inputs();
+
+    logging::init();
+    if let Some(spec) = &options.log_levels
+        && !logging::set_levels(spec)
+    {
+        println!("Invalid log levels \"{}\"", spec);
+    }
+
+    match command {
+        Command::Rom(path) => run_rom(&path, &options),
+        Command::Debug(path) => run_debug(&path, &options),
+        Command::Linked(paths) => run_linked(&paths, &options),
+        Command::Gdb(path) => run_gdb_server(&path, &options),
     }
 }
 
-fn run_rom(path: &str) {
+fn run_rom(path: &str, options: &Options) {
+    // There's only one serial port to plug things into
+    let serial_options = [&options.link_host, &options.link_connect, &options.printer];
+    if serial_options.iter().filter(|option| option.is_some()).count() > 1 {
+        println!("Only one of --link-host, --link-connect and --printer can be used at a time");
+        return;
+    }
+
     println!("\nLoading rom at: \"{}\"", path);
 
     let (mmu, mut cpu, mut ppu) = create_gameboy_components();
@@ -47,7 +82,22 @@ fn run_rom(path: &str) {
 
     emulate_boot(&mmu, &mut cpu);
 
-    let mut ui = UserInterface::new();
+    let mut profiler = options.profile.as_ref().map(|_| Profiler::new());
+    if options.code_data_log.is_some() {
+        cpu.enable_code_data_log();
+    }
+    if let Some(cable) = LinkCable::from_options(options) {
+        mmu.borrow_mut().set_serial_device(Box::new(cable));
+    } else if let Some(output_dir) = &options.printer {
+        let printer = Printer::new(Path::new(output_dir));
+        mmu.borrow_mut().set_serial_device(Box::new(printer));
+    }
+
+    let mut ui = UserInterface::new(options);
+    let mut recorder = options
+        .record
+        .as_ref()
+        .and_then(|record_path| start_recording(Path::new(record_path), &ui.palette));
 
     let render_timer_period = Duration::from_secs_f64(1.0 / 60.0);
     let mut last_render_time = Instant::now();
@@ -59,21 +109,83 @@ fn run_rom(path: &str) {
     // One loop represents one t-cycle
     while ui.running {
 
-        cpu.tick();
-        mmu.borrow_mut().tick_timers();
-        mmu.borrow_mut().tick_dma();
-        ppu.tick();
+        if let Some(profiler) = &mut profiler {
+            profiler.tick(&cpu, &mmu.borrow());
+        }
+        tick_gameboy(&mut cpu, &mmu, &mut ppu);
         // todo!
         // The ppu should eventually draw a little bit at a time.
-        // For now, just draw everything at once at 60fps
+        // For now, just draw everything at once when a frame is finished
+        if ppu.take_frame_ready() {
+            update_display(&cpu, &mut ppu);
+            let frame = ppu.get_frame();
+            ui.draw_frame(0, &frame);
+            if let Some(active) = &mut recorder
+                && let Err(error) = active.record_frame(&frame, &ui.palette)
+            {
+                println!("Recording stopped: {}", error);
+                recorder = None;
+            }
+        }
         if last_render_time.elapsed() >= render_timer_period {
             process_inputs(&mut ui, &mmu);
-            ppu.splat_tiles();
-            ui.render_display(&ppu.display);
+            ui.present();
+            if ui.inputs_unique.f12 {
+                let scale = options.screenshot_scale.unwrap_or(1);
+                screenshot::take_screenshot(path, &ppu.get_frame(), &ui.palette, scale);
+            }
+            if ui.inputs_unique.f10 {
+                recorder = toggle_recording(recorder, path, &ui);
+            }
 
             last_render_time = Instant::now();
         }
     }
+
+    if let Some(recorder) = recorder {
+        stop_recording(recorder);
+    }
+    if let (Some(profiler), Some(report_path)) = (&mut profiler, &options.profile) {
+        write_profile(profiler, path, Path::new(report_path));
+    }
+    if let (Some(log), Some(log_path)) = (cpu.code_data_log(), &options.code_data_log) {
+        match log.save(Path::new(log_path)) {
+            Ok(()) => println!("Wrote Code/Data Log to \"{}\"", log_path),
+            Err(error) => println!("Failed to write Code/Data Log to \"{}\": {}", log_path, error),
+        }
+    }
+}
+
+fn toggle_recording(
+    recorder: Option<Recorder>,
+    rom_path: &str,
+    ui: &UserInterface,
+) -> Option<Recorder> {
+    match recorder {
+        Some(recorder) => {
+            stop_recording(recorder);
+            None
+        }
+        None => {
+            let path = screenshot::next_free_path(RECORDING_DIR, rom_path, "gif");
+            start_recording(&path, &ui.palette)
+        }
+    }
+}
+
+/// Routines are only named in the report if there's a symbol file next to the ROM.
+fn write_profile(profiler: &mut Profiler, rom_path: &str, report_path: &Path) {
+    let sym_path = Path::new(rom_path).with_extension("sym");
+    let symbols = Symbols::load(&sym_path).unwrap_or_else(|_| Symbols::new());
+
+    match profiler.write_report(report_path, &symbols) {
+        Ok(()) => println!("Wrote profile to \"{}\"", report_path.display()),
+        Err(error) => println!(
+            "Failed to write profile to \"{}\": {}",
+            report_path.display(),
+            error
+        ),
+    }
 }
 
 fn create_gameboy_components() -> (Rc<RefCell<Mmu>>, Cpu, Ppu) {
@@ -83,15 +195,45 @@ fn create_gameboy_components() -> (Rc<RefCell<Mmu>>, Cpu, Ppu) {
     (mmu, cpu, ppu)
 }
 
+/// The PPU doesn't draw as it goes yet, so this draws everything at once.
+fn update_display(cpu: &Cpu, ppu: &mut Ppu) {
+    // The LCD is blank while the system is in STOP mode
+    if cpu.is_stopped() {
+        ppu.blank_display();
+    } else {
+        ppu.splat_tiles();
+    }
+}
+
+/// Progresses the whole system by one t-cycle.
+fn tick_gameboy(cpu: &mut Cpu, mmu: &Rc<RefCell<Mmu>>, ppu: &mut Ppu) {
+    cpu.tick();
+    // STOP mode halts the system clock, and everything that runs off of it
+    if cpu.is_stopped() {
+        ppu.tick_lcd_off();
+        return;
+    }
+    mmu.borrow_mut().tick_timers();
+    mmu.borrow_mut().tick_serial();
+    mmu.borrow_mut().tick_dma();
+    ppu.tick();
+}
+
 fn process_inputs(ui: &mut UserInterface, mmu: &Rc<RefCell<Mmu>>) {
     ui.process_inputs();
-    let b = ui.inputs_down.w;
-    let a_button = ui.inputs_down.a;
-    let s_button = ui.inputs_down.s;
-    let d_button = ui.inputs_down.d;
+    set_buttons(mmu, ui.inputs_down);
+}
 
-    let m_button = ui.inputs_down.m;
-    let n_button = ui.inputs_down.n;
+fn set_buttons(mmu: &Rc<RefCell<Mmu>>, inputs: Inputs) {
+    let mut mmu = mmu.borrow_mut();
+    mmu.set_button(Button::Up, inputs.w);
+    mmu.set_button(Button::Left, inputs.a);
+    mmu.set_button(Button::Down, inputs.s);
+    mmu.set_button(Button::Right, inputs.d);
+    mmu.set_button(Button::A, inputs.m);
+    mmu.set_button(Button::B, inputs.n);
+    mmu.set_button(Button::Start, inputs.enter);
+    mmu.set_button(Button::Select, inputs.backspace);
 }
 
 /// While you technically can obtain a copy of the original gameboy bootrom online,
@@ -1,4 +1,5 @@
-use crate::constants::M_CYCLE_DURATION;
+use crate::{constants::M_CYCLE_DURATION, logging::DMA};
+use log::{debug, trace};
 
 use super::{
     Mmu,
@@ -39,6 +40,7 @@ impl Mmu {
 
         self.dma.timer = DMA_TRANSFER_T_CYCLES + DMA_TRANSFER_T_DELAY;
         self.dma.source_start_addr = (dma_byte as u16) << 8;
+        debug!(target: DMA, "OAM DMA started from {:04x}", self.dma.source_start_addr);
     }
 
     pub fn tick_dma(&mut self) {
@@ -54,7 +56,6 @@ impl Mmu {
         {
             return;
         }
-        // println!("CYCLE: {}", self.dma.timer);
         self.oam_lock = true;
 
         // Copy data one byte at a time
@@ -65,10 +66,35 @@ impl Mmu {
         let byte = self.read_byte_override(source_addr);
         self.write_byte_override(target_addr, byte);
 
-        // println!("{:02x}: {:04x} => {:04x}", byte, source_addr, target_addr);
+        trace!(target: DMA, "{:02x}: {:04x} => {:04x}", byte, source_addr, target_addr);
 
         if self.dma.timer == 0 {
             self.oam_lock = false;
         }
     }
 }
+
+mod debug {
+    use super::*;
+
+    impl Mmu {
+        pub fn print_dma(&self) {
+            let dma = self.read_byte_override(DMA_ADDR);
+            println!("DMA: {:02x}", dma);
+
+            if self.dma.timer == 0 {
+                println!("Idle");
+            } else if self.dma.timer > DMA_TRANSFER_T_CYCLES {
+                println!("Starting a transfer from {:04x}", self.dma.source_start_addr);
+            } else {
+                let remaining = self.dma.timer.div_ceil(M_CYCLE_DURATION as u16);
+                println!(
+                    "Transferring from {:04x}, {}/{} bytes done",
+                    self.dma.source_start_addr,
+                    DMA_BYTE_TRANSFER_AMOUNT - remaining,
+                    DMA_BYTE_TRANSFER_AMOUNT
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! The joypad register (P1) exposes the eight buttons as a 2x4 matrix. Writing to bits 4 and 5
+//! selects the d-pad and/or the buttons, and the lower nibble then reads back which of the
+//! selected keys are held. Everything in P1 is active-low.
+//! More information available on [Pan Docs](https://gbdev.io/pandocs/Joypad_Input.html)
+
+use super::*;
+use crate::{mmu::memmap::JOYPAD_INTERRUPT_BIT, util::get_bit};
+
+const SELECT_DPAD_BIT: u8 = 4;
+const SELECT_BUTTONS_BIT: u8 = 5;
+const SELECT_MASK: u8 = 0b_0011_0000;
+/// The top two bits of P1 are unused and always read high
+const UNUSED_BITS: u8 = 0b_1100_0000;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    /// Each button shares a bit in the lower nibble of P1 with a direction on the d-pad
+    fn bit(&self) -> u8 {
+        match self {
+            Button::Right | Button::A => 0,
+            Button::Left | Button::B => 1,
+            Button::Up | Button::Select => 2,
+            Button::Down | Button::Start => 3,
+        }
+    }
+
+    fn is_dpad(&self) -> bool {
+        matches!(
+            self,
+            Button::Right | Button::Left | Button::Up | Button::Down
+        )
+    }
+}
+
+pub struct Joypad {
+    // Unlike P1, these are active-high
+    dpad: u8,
+    buttons: u8,
+    select: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            dpad: 0,
+            buttons: 0,
+            // Nothing is selected
+            select: SELECT_MASK,
+        }
+    }
+
+    fn get_p1(&self) -> u8 {
+        let mut pressed = 0;
+        if !get_bit(self.select, SELECT_DPAD_BIT) {
+            pressed |= self.dpad;
+        }
+        if !get_bit(self.select, SELECT_BUTTONS_BIT) {
+            pressed |= self.buttons;
+        }
+        UNUSED_BITS | self.select | (!pressed & 0x0F)
+    }
+}
+
+impl Mmu {
+    /// Pressing a button that is currently selected in P1 requests a joypad interrupt.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let p1_was = self.joypad.get_p1();
+
+        let group = if button.is_dpad() {
+            &mut self.joypad.dpad
+        } else {
+            &mut self.joypad.buttons
+        };
+        set_bit(group, button.bit(), pressed);
+
+        self.update_p1();
+        let p1 = self.joypad.get_p1();
+
+        // Interrupts are requested on a falling edge of any of the lower 4 bits
+        if (p1_was & !p1 & 0x0F) != 0 {
+            self.request_interrupt(JOYPAD_INTERRUPT_BIT);
+        }
+    }
+
+    /// Only the select bits of P1 are writable.
+    pub fn write_byte_p1(&mut self, byte: u8) {
+        self.joypad.select = byte & SELECT_MASK;
+        self.update_p1();
+    }
+
+    // P1 is kept up to date in IO memory, so that reads don't have to compute it
+    pub fn update_p1(&mut self) {
+        let p1 = self.joypad.get_p1();
+        self.write_byte_override(P1_ADDR, p1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_button_selection() {
+        let mmu = Mmu::new();
+        let mut mmu = mmu.borrow_mut();
+
+        mmu.set_button(Button::Start, true);
+        mmu.set_button(Button::Left, true);
+
+        // Nothing selected
+        assert_eq!(mmu.read_byte(P1_ADDR), 0xFF);
+
+        // Select buttons
+        mmu.write_byte(P1_ADDR, 0b_0001_0000);
+        assert_eq!(mmu.read_byte(P1_ADDR), 0b_1101_0111);
+
+        // Select d-pad
+        mmu.write_byte(P1_ADDR, 0b_0010_0000);
+        assert_eq!(mmu.read_byte(P1_ADDR), 0b_1110_1101);
+    }
+
+    #[test]
+    fn test_joypad_interrupt() {
+        let mmu = Mmu::new();
+        let mut mmu = mmu.borrow_mut();
+
+        // Unselected buttons don't request interrupts
+        mmu.write_byte(P1_ADDR, 0b_0010_0000);
+        mmu.set_button(Button::A, true);
+        assert!(!get_bit(mmu.read_byte(IF_ADDR), JOYPAD_INTERRUPT_BIT));
+
+        mmu.set_button(Button::Down, true);
+        assert!(get_bit(mmu.read_byte(IF_ADDR), JOYPAD_INTERRUPT_BIT));
+        assert_eq!(mmu.read_byte(P1_ADDR) & 0x0F, 0b_0111);
+    }
+}
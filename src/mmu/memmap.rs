@@ -184,6 +184,15 @@ pub fn map_addr(addr: u16) -> usize {
     (addr - start_addr) as usize
 }
 
+/// Get the bank that is currently visible at an address. There is no MBC support yet,
+/// so the switchable ROM region always holds bank 1, and everything else is bank 0.
+pub fn map_bank(addr: u16) -> u8 {
+    match addr {
+        ROM_BANK_1_START..=ROM_BANK_1_END => 1,
+        _ => 0,
+    }
+}
+
 /// Get the memory region of an address
 pub fn map_region(addr: u16) -> MemRegion {
     use MemRegion as M;
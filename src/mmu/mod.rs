@@ -10,18 +10,27 @@
 pub mod memmap;
 mod readwrite;
 mod dma;
+pub mod joypad;
+pub mod serial;
 mod timers;
+pub mod watchpoints;
 
 use dma::Dma;
+use joypad::Joypad;
 use memmap::*;
+use serial::Serial;
 use std::{cell::RefCell, rc::Rc};
 use timers::Timers;
+use watchpoints::Watchpoints;
 
 use crate::util::set_bit;
 
 pub struct Mmu {
     dma: Dma,
     timers: Timers,
+    joypad: Joypad,
+    serial: Serial,
+    watchpoints: Watchpoints,
     rom_bank_00: [u8; ROM_BANK_0_SIZE],
     rom_bank_01: [u8; ROM_BANK_1_SIZE],
     vram: [u8; VRAM_SIZE],
@@ -42,9 +51,12 @@ impl Mmu {
     //! I chose to use the Rc RefCell for the MMU so that the CPU and PPU could borrow a mutable
     //! reference to access it whenever they need to. It could just as easily be a global variable.
     pub fn new() -> Rc<RefCell<Mmu>> {
-        let mmu = Mmu {
+        let mut mmu = Mmu {
             dma: Dma::new(),
             timers: Timers::new(),
+            joypad: Joypad::new(),
+            serial: Serial::new(),
+            watchpoints: Watchpoints::new(),
             rom_bank_00: [0; ROM_BANK_0_SIZE],
             rom_bank_01: [0; ROM_BANK_1_SIZE],
             vram: [0; VRAM_SIZE],
@@ -60,6 +72,7 @@ impl Mmu {
             vram_lock: false,
             oam_lock: false,
         };
+        mmu.update_p1();
 
         Rc::new(RefCell::new(mmu))
     }
@@ -76,8 +89,52 @@ impl Mmu {
 
         true
     }
+}
+
+/// The CPU's view of memory. On a real Gameboy this is always the MMU, but
+/// the CPU can also run against flat memory for testing.
+pub trait Bus {
+    fn read_byte(&self, addr: u16) -> u8;
+    fn write_byte(&mut self, addr: u16, byte: u8);
+
+    /// Read a byte without triggering any side-effects.
+    fn read_byte_override(&self, addr: u16) -> u8;
+
+    /// Called for each of the CPU's own reads and writes, so that they can be watched.
+    fn watch_access(&self, _addr: u16, _write: bool, _value: u8) {}
+
+    /// Read a two-byte value from memory, in little-endian order
+    #[cfg(test)]
+    fn read_word(&self, addr: u16) -> u16 {
+        let lowbyte = self.read_byte(addr);
+        let highbyte = self.read_byte(addr.wrapping_add(1));
+        lowbyte as u16 | ((highbyte as u16) << 8)
+    }
+
+    /// Write a two-byte value to memory, in little-endian order
+    #[cfg(test)]
+    fn write_word(&mut self, addr: u16, word: u16) {
+        self.write_byte(addr, word as u8);
+        self.write_byte(addr.wrapping_add(1), (word >> 8) as u8);
+    }
+}
+
+impl Bus for Mmu {
+    fn read_byte(&self, addr: u16) -> u8 {
+        Mmu::read_byte(self, addr)
+    }
+
+    fn write_byte(&mut self, addr: u16, byte: u8) {
+        Mmu::write_byte(self, addr, byte);
+    }
+
+    fn read_byte_override(&self, addr: u16) -> u8 {
+        Mmu::read_byte_override(self, addr)
+    }
 
-   
+    fn watch_access(&self, addr: u16, write: bool, value: u8) {
+        self.check_watchpoints(addr, write, value);
+    }
 }
 
 mod debug {
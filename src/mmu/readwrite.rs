@@ -1,9 +1,8 @@
-/// This value is written to the serial control address to signal an output
-const TRANSFER_REQUESTED_VALUE: u8 = 0x81;
 /// Attempts to access an unavailable region of memory typically retusn all high bits
 const GARBAGE_VALUE: u8 = 0xFF;
 
 use super::*;
+
 impl Mmu {
     /// Read a byte from memory. There are many side-effects and special cases that determine
     /// how exactly the read is processed.
@@ -35,7 +34,6 @@ impl Mmu {
             }
             M::Restricted => self.restricted_memory[index],
             M::Io => match addr {
-                P1_ADDR => 0x0f,
                 IF_ADDR => self.io[index] | 0b_1110_0000, // Upper 3 bits always read high
                 _ => self.io[index],
             },
@@ -50,11 +48,6 @@ impl Mmu {
         let mem_region= map_region(addr);
         let index = map_addr(addr);
 
-        if (addr == SC_ADDR) && (byte == TRANSFER_REQUESTED_VALUE) {
-            let c = self.read_byte(SB_ADDR) as char;
-            print!("{}", c);
-        }
-
         use MemRegion as M;
         match mem_region {
             M::RomBank0 => self.rom_bank_00[index] = byte,
@@ -76,11 +69,13 @@ impl Mmu {
             M::Restricted => self.restricted_memory[index] = byte,
             // IO writes have special behaviors
             M::Io => match addr {
+                P1_ADDR => self.write_byte_p1(byte),
                 DIV_ADDR => self.write_byte_div(),
                 TMA_ADDR => self.write_byte_tma(byte),
                 TAC_ADDR => self.write_byte_tac(byte),
                 TIMA_ADDR => self.write_byte_tima(byte),
                 DMA_ADDR => self.start_dma_transfer(byte),
+                SC_ADDR => self.write_byte_sc(byte),
                 LY_ADDR => (),                                     // Read-only
                 STAT_ADDR => self.io[index] = byte & 0b_1111_1000, // Bottom 3 bits are read-only
                 IF_ADDR => self.io[index] = byte | 0b_1110_0000,   // Top 3 bits are always 1
@@ -139,21 +134,6 @@ impl Mmu {
         };
     }
 
-    /// Read a two-byte value to memory, in little-endian order
-    pub fn read_word(&self, addr: u16) -> u16 {
-        let lowbyte = self.read_byte(addr);
-        let highbyte = self.read_byte(addr + 1);
-        lowbyte as u16 | ((highbyte as u16) << 8)
-    }
-
-    /// Write a two-byte value to memory, in little-endian order
-    pub fn write_word(&mut self, addr: u16, word: u16) {
-        let lowbyte = word as u8;
-        let highbyte = (word >> 8) as u8;
-        self.write_byte(addr, lowbyte);
-        self.write_byte(addr + 1, highbyte);
-    }
-
     /// An interrupt is requested by setting a specific bit in the IF register
     pub fn request_interrupt(&mut self, interrupt_bit: u8) {
         let mut byte = self.read_byte(IF_ADDR);
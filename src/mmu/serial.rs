@@ -0,0 +1,238 @@
+//! The serial port shifts SB out to whatever is on the other end of the link cable, while
+//! shifting the other end's byte in, one bit at a time (most significant bit first).
+//! When all 8 bits have been shifted, bit 7 of SC is cleared and a serial interrupt is requested.
+//!
+//! With the internal clock (SC bit 0 set), the Game Boy drives the transfer at 8192 Hz, which is
+//! a falling edge of bit 8 of the system clock. With the external clock, it waits for the other
+//! end to start a transfer.
+//! More information available on [Pan Docs](https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html)
+
+use super::*;
+use crate::{
+    logging::SERIAL,
+    util::{get_bit, get_bit_u16},
+};
+use log::{debug, trace};
+use std::io::Write;
+
+const SERIAL_CLOCK_SYSTEM_CLOCK_BIT: u8 = 8;
+const TRANSFER_ENABLE_BIT: u8 = 7;
+const CLOCK_SELECT_BIT: u8 = 0;
+/// SC bits 1-6 are unused, and always read high
+const SC_UNUSED_BITS: u8 = 0b_0111_1110;
+/// What the Game Boy receives when nothing is driving the other end of the cable
+pub const DISCONNECTED_BYTE: u8 = 0xFF;
+
+/// Whatever is plugged into the other end of the link cable.
+/// Transfers are exchanged a byte at a time, even though the bits are shifted in one by one.
+pub trait SerialDevice {
+    /// The Game Boy started a transfer with its internal clock, sending `byte`.
+    /// Returns the byte that the device sends back.
+    fn transfer(&mut self, byte: u8) -> u8;
+
+    /// Called every t-cycle, so that the device can keep time with the Game Boy.
+    /// `waiting` is true when the Game Boy is waiting for the other end to clock a transfer,
+    /// with `sb` ready to send. Returns the byte that is sent to it, when the other end starts one.
+    fn tick(&mut self, _sb: u8, _waiting: bool) -> Option<u8> {
+        None
+    }
+}
+
+/// Nothing is plugged in, but every byte that is sent gets printed to stdout.
+/// Test ROMs (e.g. Blargg's) report their results this way.
+pub struct SerialConsole;
+
+impl SerialDevice for SerialConsole {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        print!("{}", byte as char);
+        let _ = std::io::stdout().flush();
+        DISCONNECTED_BYTE
+    }
+}
+
+pub struct Serial {
+    device: Box<dyn SerialDevice>,
+    /// The byte being shifted in, and how many of its bits are left
+    incoming_byte: u8,
+    bits_remaining: u8,
+    clock_bit_was_active: bool,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Serial {
+            device: Box::new(SerialConsole),
+            incoming_byte: 0,
+            bits_remaining: 0,
+            clock_bit_was_active: false,
+        }
+    }
+}
+
+impl Mmu {
+    pub fn set_serial_device(&mut self, device: Box<dyn SerialDevice>) {
+        self.serial.device = device;
+    }
+
+    // One tick is 1 t-cycle
+    pub fn tick_serial(&mut self) {
+        let sc = self.read_byte_override(SC_ADDR);
+        let sb = self.read_byte_override(SB_ADDR);
+
+        // Externally clocked transfers start whenever the other end is ready
+        let waiting = get_bit(sc, TRANSFER_ENABLE_BIT)
+            && !get_bit(sc, CLOCK_SELECT_BIT)
+            && self.serial.bits_remaining == 0;
+        if let Some(byte) = self.serial.device.tick(sb, waiting)
+            && waiting
+        {
+            self.begin_transfer(byte);
+        }
+
+        let clock_bit_is_active =
+            get_bit_u16(self.timers.system_clock, SERIAL_CLOCK_SYSTEM_CLOCK_BIT);
+        let falling_edge = self.serial.clock_bit_was_active && !clock_bit_is_active;
+        self.serial.clock_bit_was_active = clock_bit_is_active;
+
+        if falling_edge && self.serial.bits_remaining > 0 {
+            self.shift_bit();
+        }
+    }
+
+    fn begin_transfer(&mut self, incoming_byte: u8) {
+        debug!(
+            target: SERIAL,
+            "Transfer: sent {:02x}, received {:02x}",
+            self.read_byte_override(SB_ADDR),
+            incoming_byte
+        );
+        self.serial.incoming_byte = incoming_byte;
+        self.serial.bits_remaining = 8;
+    }
+
+    fn shift_bit(&mut self) {
+        let incoming_bit = get_bit(self.serial.incoming_byte, 7);
+        self.serial.incoming_byte <<= 1;
+
+        let sb = (self.read_byte_override(SB_ADDR) << 1) | incoming_bit as u8;
+        self.write_byte_override(SB_ADDR, sb);
+
+        self.serial.bits_remaining -= 1;
+        if self.serial.bits_remaining == 0 {
+            trace!(target: SERIAL, "Transfer complete");
+            let sc = self.read_byte_override(SC_ADDR);
+            self.write_byte_override(SC_ADDR, sc & !(1 << TRANSFER_ENABLE_BIT));
+            self.request_interrupt(SERIAL_INTERRUPT_BIT);
+        }
+    }
+
+    // ----- Special-Case Memory Reads/Writes -----
+
+    /// Setting bit 7 of SC starts a transfer. With the internal clock, the other end
+    /// of the cable has to answer right away.
+    pub fn write_byte_sc(&mut self, byte: u8) {
+        self.write_byte_override(SC_ADDR, byte | SC_UNUSED_BITS);
+
+        let internal_clock = get_bit(byte, CLOCK_SELECT_BIT);
+        if get_bit(byte, TRANSFER_ENABLE_BIT) && internal_clock {
+            let sb = self.read_byte_override(SB_ADDR);
+            let incoming_byte = self.serial.device.transfer(sb);
+            self.begin_transfer(incoming_byte);
+        } else {
+            // Clearing bit 7 cancels a transfer, and a pending external transfer starts fresh
+            self.serial.bits_remaining = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    const T_CYCLES_PER_BIT: u32 = 512;
+
+    /// Records what it was sent, and answers with a fixed byte
+    struct TestDevice {
+        sent: Rc<RefCell<Vec<u8>>>,
+        reply: u8,
+        external: bool,
+    }
+
+    impl SerialDevice for TestDevice {
+        fn transfer(&mut self, byte: u8) -> u8 {
+            self.sent.borrow_mut().push(byte);
+            self.reply
+        }
+
+        fn tick(&mut self, sb: u8, waiting: bool) -> Option<u8> {
+            if !self.external || !waiting {
+                return None;
+            }
+            self.external = false;
+            self.sent.borrow_mut().push(sb);
+            Some(self.reply)
+        }
+    }
+
+    fn tick(mmu: &mut Mmu, t_cycles: u32) {
+        for _ in 0..t_cycles {
+            mmu.tick_timers();
+            mmu.tick_serial();
+        }
+    }
+
+    fn serial_interrupt_requested(mmu: &Mmu) -> bool {
+        get_bit(mmu.read_byte_override(IF_ADDR), SERIAL_INTERRUPT_BIT)
+    }
+
+    #[test]
+    fn test_internal_clock_transfer() {
+        let mmu = Mmu::new();
+        let mut mmu = mmu.borrow_mut();
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        mmu.set_serial_device(Box::new(TestDevice {
+            sent: Rc::clone(&sent),
+            reply: 0b_1010_0101,
+            external: false,
+        }));
+
+        mmu.write_byte(SB_ADDR, 0x42);
+        mmu.write_byte(SC_ADDR, 0x81);
+        assert_eq!(*sent.borrow(), vec![0x42]);
+
+        // The bits are shifted in one at a time
+        tick(&mut mmu, 4 * T_CYCLES_PER_BIT);
+        assert_eq!(mmu.read_byte(SB_ADDR), 0x2A);
+        assert!(get_bit(mmu.read_byte(SC_ADDR), TRANSFER_ENABLE_BIT));
+        assert!(!serial_interrupt_requested(&mmu));
+
+        tick(&mut mmu, 4 * T_CYCLES_PER_BIT);
+        assert_eq!(mmu.read_byte(SB_ADDR), 0b_1010_0101);
+        assert_eq!(mmu.read_byte(SC_ADDR), 0x7F);
+        assert!(serial_interrupt_requested(&mmu));
+    }
+
+    #[test]
+    fn test_external_clock_transfer() {
+        let mmu = Mmu::new();
+        let mut mmu = mmu.borrow_mut();
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        mmu.set_serial_device(Box::new(TestDevice {
+            sent: Rc::clone(&sent),
+            reply: 0x99,
+            external: true,
+        }));
+
+        // Without an external clock, nothing happens
+        mmu.write_byte(SB_ADDR, 0x11);
+        tick(&mut mmu, 16 * T_CYCLES_PER_BIT);
+        assert!(sent.borrow().is_empty());
+
+        mmu.write_byte(SC_ADDR, 0x80);
+        tick(&mut mmu, 9 * T_CYCLES_PER_BIT);
+        assert_eq!(*sent.borrow(), vec![0x11]);
+        assert_eq!(mmu.read_byte(SB_ADDR), 0x99);
+        assert!(serial_interrupt_requested(&mmu));
+    }
+}
@@ -7,9 +7,11 @@
 
 use super::*;
 use crate::{
+    logging::TIMERS,
     mmu::memmap::{DIV_ADDR, TAC_ADDR, TIMA_ADDR, TIMER_INTERRUPT_BIT, TMA_ADDR},
     util::get_bit,
 };
+use log::trace;
 
 const TAC_FREQ_1_SYSTEM_CLOCK_BIT: u8 = 3;
 const TAC_FREQ_2_SYSTEM_CLOCK_BIT: u8 = 5;
@@ -103,6 +105,7 @@ impl Mmu {
     fn process_tima_overflow(&mut self) {
         self.timers.tima_overflowed = false;
         self.timers.tima_write_lock_counter = TIMA_WRITE_LOCK_T_CYCLES;
+        trace!(target: TIMERS, "TIMA overflowed");
 
         self.request_interrupt(TIMER_INTERRUPT_BIT);
         let tma_value = self.read_byte(TMA_ADDR);
@@ -191,18 +194,32 @@ impl Mmu {
 
 mod debug {
     use super::*;
-    use crate::mmu::memmap::{DIV_ADDR, TIMA_ADDR};
+    use crate::SYSTEM_CLOCK_FREQUENCY;
+
     impl Mmu {
-        pub fn print_timers(&mut self) {
-            let div = self.read_byte(DIV_ADDR);
-            let tima = self.read_byte(TIMA_ADDR);
-            let tac_enable = self.get_tac_enable();
+        pub fn print_timers(&self) {
+            let div = self.read_byte_override(DIV_ADDR);
+            let tima = self.read_byte_override(TIMA_ADDR);
+            let tma = self.read_byte_override(TMA_ADDR);
+            let tac = self.read_byte_override(TAC_ADDR);
             let tima_bit = self.get_system_clock_bit_for_tima();
-            println!("\nTimers:");
-            print!(
-                "\nSystem Clock: {:04x}, DIV: {:02x}, TIMA: {:02x}, TAC_ENABLE: {}, TIMA system clock bit: {:02}\n\n",
-                self.timers.system_clock, div, tima, tac_enable, tima_bit
+            // TIMA increments each time its bit falls, which happens once every 2^(bit + 1) t-cycles
+            let tima_frequency = SYSTEM_CLOCK_FREQUENCY as u32 >> (tima_bit + 1);
+
+            println!("System clock: {:04x}", self.timers.system_clock);
+            println!("DIV:  {:02x}", div);
+            println!("TIMA: {:02x}", tima);
+            println!("TMA:  {:02x}", tma);
+            println!(
+                "TAC:  {:02x} ({}, {} Hz, system clock bit {})",
+                tac,
+                if self.get_tac_enable() { "enabled" } else { "disabled" },
+                tima_frequency,
+                tima_bit
             );
+            if self.timers.tima_overflowed {
+                println!("TIMA overflowed, it will be reloaded from TMA on the next cycle");
+            }
         }
     }
 }
@@ -0,0 +1,144 @@
+//! Watchpoints catch the CPU reading or writing specific addresses. The MMU only records the
+//! accesses, it's up to the debugger to decide what to do about them.
+//! The CPU reports its own accesses through `Bus::watch_access`, so the hardware's accesses
+//! (interrupt requests, the PPU drawing, DMA) and the debugger's are never caught.
+
+use super::*;
+use std::cell::RefCell;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+impl WatchKind {
+    fn matches(&self, write: bool) -> bool {
+        match self {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub addr: u16,
+    pub kind: WatchKind,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    pub write: bool,
+    pub value: u8,
+}
+
+pub struct Watchpoints {
+    list: Vec<Watchpoint>,
+    // Reads only borrow the MMU immutably
+    hits: RefCell<Vec<WatchHit>>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Watchpoints {
+            list: Vec::new(),
+            hits: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl Mmu {
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.list.contains(&watchpoint) {
+            self.watchpoints.list.push(watchpoint);
+        }
+    }
+
+    /// Returns false if there was no such watchpoint
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let len = self.watchpoints.list.len();
+        self.watchpoints.list.retain(|w| *w != watchpoint);
+        self.watchpoints.list.len() != len
+    }
+
+    /// Returns every watchpoint hit since the last call.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        self.watchpoints.hits.take()
+    }
+
+    pub fn check_watchpoints(&self, addr: u16, write: bool, value: u8) {
+        if self.watchpoints.list.is_empty() {
+            return;
+        }
+
+        for watchpoint in &self.watchpoints.list {
+            if watchpoint.addr == addr && watchpoint.kind.matches(write) {
+                self.watchpoints.hits.borrow_mut().push(WatchHit {
+                    watchpoint: *watchpoint,
+                    write,
+                    value,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::registers::{R8, R16};
+    use crate::create_gameboy_components;
+
+    #[test]
+    fn test_watchpoints() {
+        let (mmu, mut cpu, _ppu) = create_gameboy_components();
+        for watchpoint in [
+            (0xC000, WatchKind::Write),
+            (0xC001, WatchKind::Read),
+            (IF_ADDR, WatchKind::Access),
+        ] {
+            mmu.borrow_mut().add_watchpoint(Watchpoint {
+                addr: watchpoint.0,
+                kind: watchpoint.1,
+            });
+        }
+
+        // LD (C000),A; LD A,(C001)
+        let program = [0xEA, 0x00, 0xC0, 0xFA, 0x01, 0xC0];
+        for (i, byte) in program.iter().enumerate() {
+            mmu.borrow_mut()
+                .write_byte_override(0xC100 + i as u16, *byte);
+        }
+        mmu.borrow_mut().write_byte_override(0xC001, 0x34);
+        cpu.reg.set16(R16::PC, 0xC100);
+        cpu.reg.set(R8::A, 0x12);
+        for _t_cycle in 0..32 {
+            cpu.tick();
+        }
+
+        // Neither the MMU's own accesses, nor the debugger's, are caught
+        mmu.borrow_mut().request_interrupt(TIMER_INTERRUPT_BIT);
+        mmu.borrow_mut().write_byte(0xC000, 0x56);
+        mmu.borrow_mut().write_byte_override(0xC000, 0x78);
+
+        let mut mmu = mmu.borrow_mut();
+        let hits = mmu.take_watch_hits();
+        assert_eq!(hits.len(), 2);
+        assert!(hits[0].write && hits[0].value == 0x12);
+        assert!(!hits[1].write && hits[1].value == 0x34);
+        assert!(mmu.take_watch_hits().is_empty());
+
+        assert!(mmu.remove_watchpoint(Watchpoint {
+            addr: 0xC000,
+            kind: WatchKind::Write
+        }));
+        assert!(!mmu.remove_watchpoint(Watchpoint {
+            addr: 0xC000,
+            kind: WatchKind::Write
+        }));
+    }
+}
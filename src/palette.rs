@@ -0,0 +1,134 @@
+//! The DMG only has 4 shades, and what they look like is up to the screen. The palette maps
+//! each shade to an RGB color, for the window, screenshots and recordings alike.
+//!
+//! `--palette <name>` picks one of the presets, and F9 cycles through them while running.
+//! `--palette <file>` loads one from a file instead: one color per line as `RRGGBB` or
+//! `#RRGGBB`, lightest first. Blank lines, and comments starting with `;`, are skipped.
+
+use crate::cli::Options;
+use std::path::Path;
+
+/// The RGB color of each of the 4 shades, lightest first
+pub type Palette = [[u8; 3]; 4];
+
+pub const DEFAULT_PALETTE: Palette = PRESETS[0].1;
+
+pub const PRESETS: [(&str, Palette); 5] = [
+    (
+        "green",
+        [[224, 248, 208], [136, 192, 112], [52, 104, 86], [8, 24, 32]],
+    ),
+    (
+        "pocket",
+        [
+            [224, 219, 205],
+            [168, 159, 148],
+            [112, 107, 102],
+            [43, 43, 38],
+        ],
+    ),
+    (
+        "light",
+        [[1, 203, 223], [1, 182, 213], [38, 155, 173], [0, 119, 141]],
+    ),
+    (
+        "contrast",
+        [[255, 255, 255], [170, 170, 170], [85, 85, 85], [0, 0, 0]],
+    ),
+    (
+        "inverted",
+        [[0, 0, 0], [85, 85, 85], [170, 170, 170], [255, 255, 255]],
+    ),
+];
+
+/// Returns the preset with that name, or else the palette in that file.
+pub fn load_palette(name_or_path: &str) -> Result<Palette, String> {
+    if let Some((_, palette)) = PRESETS.iter().find(|(name, _)| *name == name_or_path) {
+        return Ok(*palette);
+    }
+
+    let text = std::fs::read_to_string(Path::new(name_or_path)).map_err(|error| {
+        format!(
+            "\"{}\" isn't a preset ({}), and couldn't be read: {}",
+            name_or_path,
+            preset_names(),
+            error
+        )
+    })?;
+    parse_palette(&text)
+}
+
+/// The palette from `--palette`, or the default one if it's missing or can't be loaded.
+pub fn palette_from_options(options: &Options) -> Palette {
+    let Some(name_or_path) = &options.palette else {
+        return DEFAULT_PALETTE;
+    };
+    load_palette(name_or_path).unwrap_or_else(|error| {
+        println!("{}", error);
+        DEFAULT_PALETTE
+    })
+}
+
+/// The preset after the given palette, going back to the first after the last one.
+/// Returns its name too.
+pub fn next_preset(palette: &Palette) -> (&'static str, Palette) {
+    let index = PRESETS.iter().position(|(_, preset)| preset == palette);
+    let next = index.map_or(0, |index| (index + 1) % PRESETS.len());
+    PRESETS[next]
+}
+
+fn preset_names() -> String {
+    let names: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+    names.join(", ")
+}
+
+fn parse_palette(text: &str) -> Result<Palette, String> {
+    let colors = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .map(parse_color)
+        .collect::<Result<Vec<[u8; 3]>, String>>()?;
+
+    colors
+        .try_into()
+        .map_err(|colors: Vec<[u8; 3]>| format!("Expected 4 colors, found {}", colors.len()))
+}
+
+fn parse_color(text: &str) -> Result<[u8; 3], String> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    let invalid = || format!("Invalid color \"{}\"", text);
+    if hex.len() != 6 {
+        return Err(invalid());
+    }
+
+    let rgb = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+    Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_palette() {
+        let text = "; Lightest first\nffffff\n#C0C0C0\n\n808080\n  #000000  \n";
+        assert_eq!(
+            parse_palette(text),
+            Ok([[255, 255, 255], [192, 192, 192], [128, 128, 128], [0, 0, 0]])
+        );
+        assert!(parse_palette("ffffff\n000000").is_err());
+        assert!(parse_palette("ffffff\nc0c0c0\n808080\nblack").is_err());
+
+        assert_eq!(load_palette("inverted"), Ok(PRESETS[4].1));
+        assert!(load_palette("no-such-palette").is_err());
+    }
+
+    #[test]
+    fn test_next_preset() {
+        assert_eq!(next_preset(&DEFAULT_PALETTE).0, "pocket");
+        assert_eq!(next_preset(&PRESETS[4].1).0, "green");
+        // A palette from a file starts over from the first preset
+        assert_eq!(next_preset(&[[1, 2, 3]; 4]).0, "green");
+    }
+}
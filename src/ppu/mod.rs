@@ -4,7 +4,7 @@ mod tiles;
 
 // Timings are an integral part of the PPU
 // https://gbdev.io/pandocs/Rendering.html
-const T_CYCLES_PER_FRAME: u32 = 70224;
+pub const T_CYCLES_PER_FRAME: u32 = 70224;
 const SCANLINES_PER_FRAME: u32 = 154;
 const T_CYCLES_PER_SCANLINE: u32 = T_CYCLES_PER_FRAME / SCANLINES_PER_FRAME;
 
@@ -21,14 +21,21 @@ const OAM_SCAN_MODE_NUMBER: u8 = 2;
 const PIXEL_DRAW_MODE_NUMBER: u8 = 3;
 
 use crate::{
+    logging::PPU,
     mmu::{self, memmap::*},
     util::{get_bit, set_bit},
 };
+use log::{debug, trace};
 use mmu::Mmu;
 use std::{cell::RefCell, rc::Rc};
 
 pub type GbDisplay = [[u8; 256]; 256];
 
+// The LCD only shows a 160x144 window into the 256x256 background
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+pub type GbFrame = [[u8; SCREEN_WIDTH]; SCREEN_HEIGHT];
+
 #[repr(u8)]
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum PpuMode {
@@ -49,6 +56,10 @@ pub struct Ppu {
 
     scanline_counter: u8,
     stat_interrupt_line: bool,
+
+    /// Set when a frame is finished, until it's taken
+    frame_ready: bool,
+    lcd_off_t_cycle_count: u32,
 }
 
 impl Ppu {
@@ -64,6 +75,9 @@ impl Ppu {
 
             scanline_counter: 0,
             stat_interrupt_line: false,
+
+            frame_ready: false,
+            lcd_off_t_cycle_count: 0,
         }
     }
 
@@ -75,13 +89,18 @@ impl Ppu {
         // You're not supposed to turn off the PPU outside of vblank mode, but from
         // what I can tell, the hardware won't prevent it
         if self.was_enabled && !ppu_enabled {
+            debug!(target: PPU, "LCD turned off during {:?}", ppu_mode);
             self.turn_off();
+        } else if !self.was_enabled && ppu_enabled {
+            debug!(target: PPU, "LCD turned on");
         }
         self.was_enabled = ppu_enabled;
 
         if !ppu_enabled {
+            self.tick_lcd_off();
             return;
         }
+        self.lcd_off_t_cycle_count = 0;
 
         self.frame_t_cycle_count += 1;
         self.scanline_t_cycle_count += 1;
@@ -110,7 +129,9 @@ impl Ppu {
             PpuMode::HBlank => {
                 // HBLANK -> VBLANK
                 if self.frame_t_cycle_count == T_CYCLES_PER_FRAME - VBLANK_T_CYCLES {
+                    trace!(target: PPU, "Entered VBlank");
                     self.set_mode(PpuMode::VBlank);
+                    self.frame_ready = true;
                     self.mmu
                         .borrow_mut()
                         .request_interrupt(VBLANK_INTERRUPT_BIT);
@@ -124,7 +145,6 @@ impl Ppu {
             }
             PpuMode::VBlank => {
                 if self.frame_t_cycle_count == T_CYCLES_PER_FRAME {
-                    // println!("VBLANK -> OAM");
                     self.set_mode(PpuMode::OamScan);
                     self.mmu.borrow_mut().oam_lock = true;
                 }
@@ -147,6 +167,22 @@ impl Ppu {
         // self.update_ppu_status_registers();
     }
 
+    /// Progresses by one t-cycle while the LCD shows nothing, with the LCD off or the system in
+    /// STOP mode. There are no VBlanks, but a frame still goes by every frame's worth of t-cycles.
+    pub fn tick_lcd_off(&mut self) {
+        self.lcd_off_t_cycle_count += 1;
+        if self.lcd_off_t_cycle_count == T_CYCLES_PER_FRAME {
+            self.lcd_off_t_cycle_count = 0;
+            self.frame_ready = true;
+        }
+    }
+
+    /// Returns true once for each finished frame, as VBlank starts. Anything that needs to keep
+    /// in step with the frames (blending, recording) goes by this rather than by the clock.
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
     fn update_ppu_status_registers(&mut self) {
         let ly = self.read_byte(LY_ADDR);
 
@@ -217,6 +253,25 @@ impl Ppu {
         mmu.oam_lock = false;
     }
 
+    pub fn blank_display(&mut self) {
+        self.display = [[0; 256]; 256];
+    }
+
+    /// Crop the visible part of the display, as selected by the scroll registers.
+    /// The background wraps around at its edges.
+    pub fn get_frame(&self) -> GbFrame {
+        let scx = self.read_byte(SCX_ADDR) as usize;
+        let scy = self.read_byte(SCY_ADDR) as usize;
+
+        let mut frame: GbFrame = [[0; SCREEN_WIDTH]; SCREEN_HEIGHT];
+        for (y, row) in frame.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = self.display[(y + scy) % 256][(x + scx) % 256];
+            }
+        }
+        frame
+    }
+
     /// The PPU is not write-locked from VRAM or OAM
     fn read_byte(&self, addr: u16) -> u8 {
         let region = map_region(addr);
@@ -322,4 +377,36 @@ mod tests {
     //     // assert_eq!(ly_to_compare_lyc, false, "LY to compare LYC");
     //     assert_eq!(if_flag, if_flag_expect, "IF Flag (LY=LYC)");
     // }
+
+    #[test]
+    fn test_frame_ready() {
+        let (_mmu, _cpu, mut ppu) = create_gameboy_components();
+
+        ppu.set_lcdc_flag(LCD_AND_PPU_ENABLE_BIT, true);
+        let first_frame = (0..T_CYCLES_PER_FRAME)
+            .position(|_| {
+                ppu.tick();
+                ppu.take_frame_ready()
+            })
+            .unwrap();
+        assert_eq!(first_frame as u32, T_CYCLES_PER_FRAME - VBLANK_T_CYCLES - 1);
+        assert!(!ppu.take_frame_ready());
+        let frames = (0..T_CYCLES_PER_FRAME * 3)
+            .filter(|_| {
+                ppu.tick();
+                ppu.take_frame_ready()
+            })
+            .count();
+        assert_eq!(frames, 3);
+
+        // Frames keep going by with the LCD off
+        ppu.set_lcdc_flag(LCD_AND_PPU_ENABLE_BIT, false);
+        let frames = (0..T_CYCLES_PER_FRAME * 2)
+            .filter(|_| {
+                ppu.tick();
+                ppu.take_frame_ready()
+            })
+            .count();
+        assert_eq!(frames, 2);
+    }
 }
@@ -0,0 +1,384 @@
+//! The Game Boy Printer, plugged into the serial port. Each time a game prints, the strip of
+//! paper that comes out is saved as a PNG, numbered in order (`print-001.png`, ...).
+//!
+//! The game drives every transfer, and sends the printer packets:
+//! `88 33 <command> <compression> <length (2)> <data> <checksum (2)> 00 00`.
+//! The printer answers 81 on the first of the last two bytes to show it's there, and its
+//! status on the second. Image data is sent as tiles, 20 to a row like the screen, and printed
+//! with the palette from the print command.
+//! More information available on [Pan Docs](https://gbdev.io/pandocs/Gameboy_Printer.html)
+
+use crate::image::{RgbImage, save_png};
+use crate::mmu::serial::SerialDevice;
+use std::path::{Path, PathBuf};
+
+const MAGIC_BYTES: [u8; 2] = [0x88, 0x33];
+const ALIVE_BYTE: u8 = 0x81;
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_BREAK: u8 = 0x08;
+const COMMAND_STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_BUSY: u8 = 0x02;
+const STATUS_IMAGE_DATA_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED_DATA: u8 = 0x08;
+const STATUS_PACKET_ERROR: u8 = 0x10;
+
+/// The printer's memory holds 9 rows of tiles, 20 tiles wide
+const IMAGE_DATA_SIZE: usize = 0x2000;
+const TILES_PER_ROW: usize = 20;
+const BYTES_PER_TILE: usize = 16;
+const PAPER_WIDTH: usize = TILES_PER_ROW * 8;
+
+/// What colors 0-3 look like on paper, after the palette has been applied
+const PAPER_SHADES: [[u8; 3]; 4] = [
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+    [0x00, 0x00, 0x00],
+];
+
+/// Where the printer is in the packet, i.e. what the next byte it receives is
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+#[derive(Default)]
+struct Packet {
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+}
+
+impl Packet {
+    /// The checksum covers everything after the magic bytes, up to the checksum itself
+    fn calculate_checksum(&self) -> u16 {
+        let header = [
+            self.command,
+            self.compressed as u8,
+            self.length as u8,
+            (self.length >> 8) as u8,
+        ];
+        header
+            .iter()
+            .chain(&self.data)
+            .fold(0, |sum: u16, byte| sum.wrapping_add(*byte as u16))
+    }
+}
+
+pub struct Printer {
+    output_dir: PathBuf,
+    prints: usize,
+    state: State,
+    packet: Packet,
+    image_data: Vec<u8>,
+    status: u8,
+}
+
+impl Printer {
+    pub fn new(output_dir: &Path) -> Self {
+        Printer {
+            output_dir: output_dir.to_path_buf(),
+            prints: 0,
+            state: State::Magic1,
+            packet: Packet::default(),
+            image_data: Vec::new(),
+            status: 0,
+        }
+    }
+
+    fn receive(&mut self, byte: u8) {
+        let packet = &mut self.packet;
+        self.state = match self.state {
+            State::Magic1 if byte == MAGIC_BYTES[0] => State::Magic2,
+            State::Magic1 => State::Magic1,
+            State::Magic2 if byte == MAGIC_BYTES[1] => State::Command,
+            State::Magic2 if byte == MAGIC_BYTES[0] => State::Magic2,
+            State::Magic2 => State::Magic1,
+            State::Command => {
+                *packet = Packet {
+                    command: byte,
+                    ..Packet::default()
+                };
+                State::Compression
+            }
+            State::Compression => {
+                packet.compressed = byte & 1 != 0;
+                State::LengthLow
+            }
+            State::LengthLow => {
+                packet.length = byte as u16;
+                State::LengthHigh
+            }
+            State::LengthHigh => {
+                packet.length |= (byte as u16) << 8;
+                match packet.length {
+                    0 => State::ChecksumLow,
+                    _ => State::Data,
+                }
+            }
+            State::Data => {
+                packet.data.push(byte);
+                match packet.data.len() < packet.length as usize {
+                    true => State::Data,
+                    false => State::ChecksumLow,
+                }
+            }
+            State::ChecksumLow => {
+                packet.checksum = byte as u16;
+                State::ChecksumHigh
+            }
+            State::ChecksumHigh => {
+                packet.checksum |= (byte as u16) << 8;
+                self.process_packet();
+                State::Alive
+            }
+            State::Alive => State::Status,
+            State::Status => {
+                // Printing is done by the time the game checks on it
+                if self.packet.command == COMMAND_STATUS {
+                    self.status &= !STATUS_BUSY;
+                }
+                State::Magic1
+            }
+        };
+    }
+
+    fn process_packet(&mut self) {
+        if self.packet.checksum != self.packet.calculate_checksum() {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.packet.command {
+            COMMAND_INIT | COMMAND_BREAK => {
+                self.image_data.clear();
+                self.status = 0;
+            }
+            COMMAND_DATA => {
+                let data = match self.packet.compressed {
+                    true => decompress(&self.packet.data),
+                    false => self.packet.data.clone(),
+                };
+                let space = IMAGE_DATA_SIZE - self.image_data.len();
+                self.image_data
+                    .extend_from_slice(&data[..data.len().min(space)]);
+
+                if !self.image_data.is_empty() {
+                    self.status |= STATUS_UNPROCESSED_DATA;
+                }
+                if self.image_data.len() == IMAGE_DATA_SIZE {
+                    self.status |= STATUS_IMAGE_DATA_FULL;
+                }
+            }
+            COMMAND_PRINT => {
+                // Sheets, margins, palette, exposure
+                let palette = self.packet.data.get(2).copied().unwrap_or(0);
+                self.print(palette);
+                self.image_data.clear();
+                self.status = STATUS_BUSY;
+            }
+            COMMAND_STATUS => {}
+            _ => self.status |= STATUS_PACKET_ERROR,
+        }
+    }
+
+    fn print(&mut self, palette: u8) {
+        let Some(image) = decode_image(&self.image_data, palette) else {
+            return;
+        };
+
+        self.prints += 1;
+        let path = self
+            .output_dir
+            .join(format!("print-{:03}.png", self.prints));
+        match save_png(&path, &image) {
+            Ok(()) => println!("Printed to \"{}\"", path.display()),
+            Err(error) => println!("Failed to print to \"{}\": {}", path.display(), error),
+        }
+    }
+}
+
+impl SerialDevice for Printer {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        // The reply is shifted out at the same time as the byte is shifted in, so it depends on
+        // where the printer was in the packet, not on the byte
+        let reply = match self.state {
+            State::Alive => ALIVE_BYTE,
+            State::Status => self.status,
+            _ => 0x00,
+        };
+        self.receive(byte);
+        reply
+    }
+}
+
+/// Runs are a control byte with bit 7 set, then a byte to repeat (control & 0x7F) + 2 times.
+/// Otherwise, the control byte is followed by control + 1 bytes to copy as is.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut bytes = data.iter().copied();
+
+    while let Some(control) = bytes.next() {
+        if control & 0x80 != 0 {
+            let Some(byte) = bytes.next() else { break };
+            let count = (control & 0x7F) as usize + 2;
+            output.extend(std::iter::repeat_n(byte, count));
+        } else {
+            let count = control as usize + 1;
+            output.extend(bytes.by_ref().take(count));
+        }
+    }
+    output
+}
+
+/// Returns None if there's not a whole row of tiles to print.
+/// A palette of 00 is treated as the usual E4, the same as the printer does.
+fn decode_image(image_data: &[u8], palette: u8) -> Option<RgbImage> {
+    let palette = match palette {
+        0 => 0xE4,
+        palette => palette,
+    };
+    let tile_rows = image_data.len() / (TILES_PER_ROW * BYTES_PER_TILE);
+    if tile_rows == 0 {
+        return None;
+    }
+
+    let mut image = RgbImage::new(PAPER_WIDTH, tile_rows * 8);
+    for (tile_index, tile) in image_data.chunks_exact(BYTES_PER_TILE).enumerate() {
+        let tile_x = (tile_index % TILES_PER_ROW) * 8;
+        let tile_y = (tile_index / TILES_PER_ROW) * 8;
+        if tile_y >= image.height {
+            break;
+        }
+
+        for (row, bytes) in tile.chunks_exact(2).enumerate() {
+            for column in 0..8 {
+                let bit = 7 - column;
+                let color = ((bytes[0] >> bit) & 1) | (((bytes[1] >> bit) & 1) << 1);
+                let shade = (palette >> (color * 2)) & 0b11;
+                image.set(tile_x + column, tile_y + row, PAPER_SHADES[shade as usize]);
+            }
+        }
+    }
+    Some(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends a whole packet, and returns the printer's replies to the last two bytes
+    fn send_packet(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> [u8; 2] {
+        let packet = Packet {
+            command,
+            compressed,
+            length: data.len() as u16,
+            data: data.to_vec(),
+            checksum: 0,
+        };
+        let checksum = packet.calculate_checksum();
+
+        let mut bytes = vec![0x88, 0x33, command, compressed as u8];
+        bytes.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+
+        for byte in bytes {
+            assert_eq!(printer.transfer(byte), 0x00);
+        }
+        [printer.transfer(0x00), printer.transfer(0x00)]
+    }
+
+    #[test]
+    fn test_decompress() {
+        let data = [0x81, 0xAA, 0x02, 0x01, 0x02, 0x03, 0x80, 0xFF];
+        assert_eq!(
+            decompress(&data),
+            vec![0xAA, 0xAA, 0xAA, 0x01, 0x02, 0x03, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn test_print() {
+        let output_dir = std::env::temp_dir().join("gameboy-emulator-printer");
+        let mut printer = Printer::new(&output_dir);
+
+        assert_eq!(
+            send_packet(&mut printer, COMMAND_INIT, false, &[]),
+            [ALIVE_BYTE, 0x00]
+        );
+
+        // A row of tiles, where the first tile's top row is colors 3,3,2,2,1,1,0,0
+        let mut tiles = vec![0; TILES_PER_ROW * BYTES_PER_TILE];
+        tiles[0] = 0b_1100_1100;
+        tiles[1] = 0b_1111_0000;
+        // The same row, compressed: 2 literal bytes, then runs of 129, 129 and 60 zeros
+        let compressed = [
+            0x01,
+            0b_1100_1100,
+            0b_1111_0000,
+            0xFF,
+            0x00,
+            0xFF,
+            0x00,
+            0xBA,
+            0x00,
+        ];
+        assert_eq!(decompress(&compressed), tiles);
+
+        let status = send_packet(&mut printer, COMMAND_DATA, false, &tiles);
+        assert_eq!(status, [ALIVE_BYTE, STATUS_UNPROCESSED_DATA]);
+        send_packet(&mut printer, COMMAND_DATA, true, &compressed);
+        assert_eq!(printer.image_data.len(), 2 * tiles.len());
+
+        // A bad checksum is reported, and the packet is ignored
+        for byte in [0x88, 0x33, COMMAND_DATA, 0, 1, 0, 0xFF, 0x00, 0x00, 0x00] {
+            printer.transfer(byte);
+        }
+        let status = printer.transfer(0x00);
+        assert_eq!(status, STATUS_UNPROCESSED_DATA | STATUS_CHECKSUM_ERROR);
+        assert_eq!(printer.image_data.len(), 2 * tiles.len());
+
+        let image = decode_image(&printer.image_data, 0xE4).unwrap();
+        assert_eq!((image.width, image.height), (160, 16));
+        assert_eq!(image.get(0, 0), PAPER_SHADES[3]);
+        assert_eq!(image.get(2, 0), PAPER_SHADES[2]);
+        assert_eq!(image.get(4, 0), PAPER_SHADES[1]);
+        assert_eq!(image.get(6, 0), PAPER_SHADES[0]);
+        assert_eq!(image.get(0, 8), PAPER_SHADES[3]);
+        // The palette maps colors to shades, here inverting them
+        let image = decode_image(&printer.image_data, 0x1B).unwrap();
+        assert_eq!(image.get(0, 0), PAPER_SHADES[0]);
+
+        // Sheets, margins, palette, exposure
+        let status = send_packet(&mut printer, COMMAND_PRINT, false, &[1, 0x13, 0xE4, 0x40]);
+        assert_eq!(status, [ALIVE_BYTE, STATUS_BUSY]);
+        let path = output_dir.join("print-001.png");
+        assert!(path.exists());
+        std::fs::remove_dir_all(&output_dir).ok();
+
+        // The game sees it printing once, then finished
+        let status = send_packet(&mut printer, COMMAND_STATUS, false, &[]);
+        assert_eq!(status, [ALIVE_BYTE, STATUS_BUSY]);
+        let status = send_packet(&mut printer, COMMAND_STATUS, false, &[]);
+        assert_eq!(status, [ALIVE_BYTE, 0x00]);
+    }
+}
@@ -0,0 +1,228 @@
+//! The profiler counts how many instructions ran, and how many t-cycles they took, at each
+//! address and for each opcode. Addresses are tracked along with the ROM bank they were in,
+//! so that code in different banks at the same address isn't lumped together.
+//!
+//! Cycles are charged to the instruction that was running, which includes the time spent
+//! halted after a HALT, and dispatching any interrupt that follows it. That way, the report
+//! adds up to the whole run, and idle time (e.g. waiting for VBlank) shows up as such.
+
+use crate::cpu::{Cpu, registers::R16};
+use crate::debugger::symbols::Symbols;
+use crate::mmu::{
+    Mmu,
+    memmap::{MemRegion, map_bank, map_region},
+};
+use crate::ppu::T_CYCLES_PER_FRAME;
+use std::{collections::HashMap, fmt::Write, io, path::Path};
+
+const PREFIX_OPCODE: u8 = 0xCB;
+/// Prefixed opcodes are counted after the 256 unprefixed ones
+const OPCODE_COUNT: usize = 512;
+const REPORT_LINES: usize = 20;
+
+#[derive(Clone, Copy, Default)]
+struct Counts {
+    instructions: u64,
+    cycles: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.instructions += other.instructions;
+        self.cycles += other.cycles;
+    }
+}
+
+pub struct Profiler {
+    locations: HashMap<(u8, u16), Counts>,
+    opcodes: [Counts; OPCODE_COUNT],
+    total: Counts,
+    /// The instruction that is running, and the cycles it has taken so far
+    current: Option<((u8, u16), usize)>,
+    current_cycles: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            locations: HashMap::new(),
+            opcodes: [Counts::default(); OPCODE_COUNT],
+            total: Counts::default(),
+            current: None,
+            current_cycles: 0,
+        }
+    }
+
+    /// Must be called before every t-cycle, while the CPU is in the state it will tick from.
+    pub fn tick(&mut self, cpu: &Cpu, mmu: &Mmu) {
+        if cpu.at_instruction_boundary() {
+            self.finish_instruction();
+
+            let pc = cpu.reg.get16(R16::PC);
+            let opcode = match mmu.read_byte_override(pc) {
+                PREFIX_OPCODE => 0x100 + mmu.read_byte_override(pc.wrapping_add(1)) as usize,
+                opcode => opcode as usize,
+            };
+            let location = (map_bank(pc), pc);
+
+            self.locations.entry(location).or_default().instructions += 1;
+            self.opcodes[opcode].instructions += 1;
+            self.total.instructions += 1;
+            self.current = Some((location, opcode));
+        }
+
+        self.current_cycles += 1;
+    }
+
+    /// Charges the cycles counted so far to the instruction that was running.
+    fn finish_instruction(&mut self) {
+        let cycles = std::mem::take(&mut self.current_cycles);
+        self.total.cycles += cycles;
+
+        if let Some((location, opcode)) = self.current {
+            self.locations.entry(location).or_default().cycles += cycles;
+            self.opcodes[opcode].cycles += cycles;
+        }
+    }
+
+    pub fn write_report(&mut self, path: &Path, symbols: &Symbols) -> io::Result<()> {
+        std::fs::write(path, self.report(symbols))
+    }
+
+    pub fn report(&mut self, symbols: &Symbols) -> String {
+        self.finish_instruction();
+
+        let mut report = String::new();
+        let frames = self.total.cycles as f64 / T_CYCLES_PER_FRAME as f64;
+        writeln!(
+            report,
+            "{} instructions, {} t-cycles ({:.1} frames)",
+            self.total.instructions, self.total.cycles, frames
+        )
+        .unwrap();
+
+        // Without symbols, the best that can be done is to list the hottest addresses
+        if symbols.len() > 0 {
+            writeln!(report, "\nHottest routines:").unwrap();
+            self.write_table(&mut report, self.count_routines(symbols));
+        }
+
+        writeln!(report, "\nHottest addresses:").unwrap();
+        let locations = self.locations.iter().map(|(&(bank, addr), counts)| {
+            let name = match symbols.find_label(bank, addr) {
+                Some((label_addr, label)) if label_addr == addr => format!(" <{}>", label),
+                Some((label_addr, label)) => format!(" <{}+${:x}>", label, addr - label_addr),
+                None => String::new(),
+            };
+            (format!("{:02x}:{:04x}{}", bank, addr, name), *counts)
+        });
+        self.write_table(&mut report, locations.collect());
+
+        writeln!(report, "\nHottest opcodes:").unwrap();
+        let opcodes = self
+            .opcodes
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.instructions > 0);
+        let opcodes = opcodes.map(|(opcode, counts)| {
+            let name = if opcode >= 0x100 {
+                format!("{:02x} {:02x}", PREFIX_OPCODE, opcode - 0x100)
+            } else {
+                format!("{:02x}", opcode)
+            };
+            (name, *counts)
+        });
+        self.write_table(&mut report, opcodes.collect());
+
+        report
+    }
+
+    /// Adds up every address under the label it belongs to. Local labels (`Main.loop`)
+    /// are counted as part of their parent routine. Code in RAM without a label there (e.g. an
+    /// OAM DMA routine copied to HRAM) is counted on its own.
+    fn count_routines(&self, symbols: &Symbols) -> Vec<(String, Counts)> {
+        let mut routines: HashMap<String, Counts> = HashMap::new();
+
+        for (&(bank, addr), counts) in &self.locations {
+            let in_rom = matches!(map_region(addr), MemRegion::RomBank0 | MemRegion::RomBank1);
+            let routine = match symbols.find_label(bank, addr) {
+                Some((_, label)) => label.split('.').next().unwrap().to_string(),
+                None if in_rom => "(unknown)".to_string(),
+                None => "(RAM)".to_string(),
+            };
+            routines.entry(routine).or_default().add(*counts);
+        }
+
+        routines.into_iter().collect()
+    }
+
+    /// Writes the rows with the most cycles, hottest first.
+    fn write_table(&self, report: &mut String, mut rows: Vec<(String, Counts)>) {
+        rows.sort_by(|(a_name, a), (b_name, b)| b.cycles.cmp(&a.cycles).then(a_name.cmp(b_name)));
+
+        writeln!(
+            report,
+            "{:>12} {:>7} {:>12}  name",
+            "t-cycles", "%", "instructions"
+        )
+        .unwrap();
+        for (name, counts) in rows.iter().take(REPORT_LINES) {
+            let percent = 100.0 * counts.cycles as f64 / self.total.cycles.max(1) as f64;
+            writeln!(
+                report,
+                "{:>12} {:>6.2}% {:>12}  {}",
+                counts.cycles, percent, counts.instructions, name
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_gameboy_components;
+
+    #[test]
+    fn test_profile() {
+        let (mmu, mut cpu, _ppu) = create_gameboy_components();
+        cpu.reg.set16(R16::PC, 0xC000);
+
+        // Loop: NOP, SWAP A, JR Loop
+        let program = [0x00, 0xCB, 0x37, 0x18, 0xFB];
+        for (i, byte) in program.iter().enumerate() {
+            mmu.borrow_mut()
+                .write_byte_override(0xC000 + i as u16, *byte);
+        }
+
+        let mut profiler = Profiler::new();
+        // Each loop takes 4 + 8 + 12 t-cycles
+        for _t_cycle in 0..24 * 10 {
+            profiler.tick(&cpu, &mmu.borrow());
+            cpu.tick();
+        }
+        profiler.finish_instruction();
+
+        assert_eq!(profiler.total.instructions, 30);
+        assert_eq!(profiler.total.cycles, 240);
+        assert_eq!(profiler.locations[&(0, 0xC000)].cycles, 40);
+        assert_eq!(profiler.opcodes[0x137].cycles, 80);
+        assert_eq!(profiler.opcodes[0x18].instructions, 10);
+
+        let symbols = Symbols::parse("00:c000 Loop\n00:c003 Loop.jump");
+        let routines = profiler.count_routines(&symbols);
+        assert_eq!(routines.len(), 1);
+        assert_eq!(routines[0].0, "Loop");
+        assert_eq!(routines[0].1.cycles, 240);
+
+        let report = profiler.report(&symbols);
+        assert!(report.starts_with("30 instructions, 240 t-cycles"));
+        assert!(report.contains("00:c003 <Loop.jump>"));
+
+        // The last label in ROM bank 0 doesn't cover code in RAM
+        let symbols = Symbols::parse("00:0150 Main");
+        let routines = profiler.count_routines(&symbols);
+        assert_eq!(routines.len(), 1);
+        assert_eq!(routines[0].0, "(RAM)");
+    }
+}
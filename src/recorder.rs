@@ -0,0 +1,264 @@
+//! Records gameplay, one frame per emulated frame, so recordings play back at the Game Boy's own
+//! 59.73 fps however fast the emulator ran. A frame is recorded each time the PPU finishes one
+//! (see `Ppu::take_frame_ready`), which keeps going while the LCD is off.
+//!
+//! The format comes from the file extension:
+//! - `.gif` is an animated GIF. GIF delays are in 1/100ths of a second, so each frame's delay is
+//!   rounded from where it starts and ends in time, which keeps the timing from drifting.
+//!   Runs of identical frames are merged into one.
+//! - `.y4m` is uncompressed YUV 4:4:4 video with the exact frame rate (4194304/70224 fps),
+//!   for muxing or converting with e.g. ffmpeg.
+//!
+//! There's no APU yet, so there's no audio to record.
+
+use crate::SYSTEM_CLOCK_FREQUENCY;
+use crate::ppu::{GbFrame, SCREEN_HEIGHT, SCREEN_WIDTH, T_CYCLES_PER_FRAME};
+use crate::palette::Palette;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+pub const RECORDING_DIR: &str = "recordings";
+
+/// Keeps each merged GIF frame's delay within a u16
+const MAX_GIF_FRAME_RUN: u64 = 1000;
+
+pub struct Recorder {
+    path: PathBuf,
+    output: Output,
+    frames: u64,
+}
+
+enum Output {
+    Gif(Box<GifOutput>),
+    Y4m(BufWriter<File>),
+}
+
+struct GifOutput {
+    encoder: gif::Encoder<BufWriter<File>>,
+    global_palette: Palette,
+    /// The frame waiting to be written, which frame it started on, and its palette
+    pending: Option<(GbFrame, u64, Palette)>,
+}
+
+impl Recorder {
+    /// Starts recording to the path, in the format from its extension.
+    pub fn start(path: &Path, palette: &Palette) -> io::Result<Recorder> {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let output = match extension.map(str::to_lowercase).as_deref() {
+            Some("gif") => Output::Gif(Box::new(GifOutput::new(create_file(path)?, palette)?)),
+            Some("y4m") => {
+                let mut file = create_file(path)?;
+                writeln!(
+                    file,
+                    "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+                    SCREEN_WIDTH, SCREEN_HEIGHT, SYSTEM_CLOCK_FREQUENCY as u32, T_CYCLES_PER_FRAME
+                )?;
+                Output::Y4m(file)
+            }
+            _ => return Err(io::Error::other("recordings must be .gif or .y4m")),
+        };
+
+        Ok(Recorder {
+            path: path.to_path_buf(),
+            output,
+            frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record_frame(&mut self, frame: &GbFrame, palette: &Palette) -> io::Result<()> {
+        match &mut self.output {
+            Output::Gif(gif) => gif.record_frame(frame, self.frames, palette)?,
+            Output::Y4m(file) => {
+                file.write_all(b"FRAME\n")?;
+                file.write_all(&frame_to_yuv(frame, palette))?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Writes anything that's still pending, and returns how many frames were recorded.
+    pub fn finish(self) -> io::Result<u64> {
+        match self.output {
+            Output::Gif(mut gif) => {
+                gif.flush(self.frames)?;
+                gif.encoder
+                    .into_inner()
+                    .map_err(io::Error::other)?
+                    .flush()?;
+            }
+            Output::Y4m(mut file) => file.flush()?,
+        }
+        Ok(self.frames)
+    }
+}
+
+/// Says where the recording is going, or why it couldn't start.
+pub fn start_recording(path: &Path, palette: &Palette) -> Option<Recorder> {
+    match Recorder::start(path, palette) {
+        Ok(recorder) => {
+            println!("Recording to \"{}\"", path.display());
+            Some(recorder)
+        }
+        Err(error) => {
+            println!("Failed to record to \"{}\": {}", path.display(), error);
+            None
+        }
+    }
+}
+
+pub fn stop_recording(recorder: Recorder) {
+    let path = recorder.path().to_path_buf();
+    match recorder.finish() {
+        Ok(frames) => println!("Recorded {} frames to \"{}\"", frames, path.display()),
+        Err(error) => println!("Failed to record to \"{}\": {}", path.display(), error),
+    }
+}
+
+fn create_file(path: &Path) -> io::Result<BufWriter<File>> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+impl GifOutput {
+    fn new(file: BufWriter<File>, palette: &Palette) -> io::Result<Self> {
+        let mut encoder = gif::Encoder::new(
+            file,
+            SCREEN_WIDTH as u16,
+            SCREEN_HEIGHT as u16,
+            palette.as_flattened(),
+        )
+        .map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+
+        Ok(GifOutput {
+            encoder,
+            global_palette: *palette,
+            pending: None,
+        })
+    }
+
+    fn record_frame(&mut self, frame: &GbFrame, index: u64, palette: &Palette) -> io::Result<()> {
+        if let Some((pending, start, pending_palette)) = &self.pending
+            && pending == frame
+            && pending_palette == palette
+            && index - start < MAX_GIF_FRAME_RUN
+        {
+            return Ok(());
+        }
+
+        self.flush(index)?;
+        self.pending = Some((*frame, index, *palette));
+        Ok(())
+    }
+
+    /// Writes the pending frame, which lasts until `end` (a frame index).
+    fn flush(&mut self, end: u64) -> io::Result<()> {
+        let Some((frame, start, palette)) = self.pending.take() else {
+            return Ok(());
+        };
+
+        // The shades are the palette indices, so the pixels go in as they are
+        let mut gif_frame = gif::Frame {
+            width: SCREEN_WIDTH as u16,
+            height: SCREEN_HEIGHT as u16,
+            delay: (centiseconds(end) - centiseconds(start)) as u16,
+            buffer: frame.as_flattened().to_vec().into(),
+            ..gif::Frame::default()
+        };
+        // Only needed if the palette was changed while recording
+        if palette != self.global_palette {
+            gif_frame.palette = Some(palette.as_flattened().to_vec());
+        }
+        self.encoder
+            .write_frame(&gif_frame)
+            .map_err(io::Error::other)
+    }
+}
+
+/// When a frame starts, rounded to 1/100ths of a second
+fn centiseconds(frame: u64) -> u64 {
+    let clock = SYSTEM_CLOCK_FREQUENCY as u64;
+    (frame * T_CYCLES_PER_FRAME as u64 * 100 + clock / 2) / clock
+}
+
+/// Planar Y, Cb then Cr, with the BT.601 limited range that players expect.
+fn frame_to_yuv(frame: &GbFrame, palette: &Palette) -> Vec<u8> {
+    let colors = palette.map(|[r, g, b]| {
+        let (r, g, b) = (r as f64, g as f64, b as f64);
+        [
+            16.0 + 0.257 * r + 0.504 * g + 0.098 * b,
+            128.0 - 0.148 * r - 0.291 * g + 0.439 * b,
+            128.0 + 0.439 * r - 0.368 * g - 0.071 * b,
+        ]
+        .map(|value| value.round() as u8)
+    });
+
+    let pixels = frame.as_flattened();
+    (0..3)
+        .flat_map(|plane| pixels.iter().map(move |&shade| (plane, shade)))
+        .map(|(plane, shade)| colors[shade as usize][plane])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::DEFAULT_PALETTE;
+
+    #[test]
+    fn test_centiseconds() {
+        // 59.73 fps is about 1.67 centiseconds a frame, so delays alternate without drifting
+        let delays: Vec<u64> = (0..6)
+            .map(|frame| centiseconds(frame + 1) - centiseconds(frame))
+            .collect();
+        assert_eq!(delays, vec![2, 1, 2, 2, 1, 2]);
+        assert_eq!(centiseconds(5973), 10000);
+    }
+
+    #[test]
+    fn test_record() {
+        let mut frame: GbFrame = [[0; SCREEN_WIDTH]; SCREEN_HEIGHT];
+        let dir = std::env::temp_dir().join("gameboy-emulator-recorder");
+
+        let path = dir.join("test.y4m");
+        let mut recorder = Recorder::start(&path, &DEFAULT_PALETTE).unwrap();
+        recorder.record_frame(&frame, &DEFAULT_PALETTE).unwrap();
+        frame[0][0] = 3;
+        recorder.record_frame(&frame, &DEFAULT_PALETTE).unwrap();
+        assert_eq!(recorder.finish().unwrap(), 2);
+
+        let y4m = std::fs::read(&path).unwrap();
+        let header = b"YUV4MPEG2 W160 H144 F4194304:70224 Ip A1:1 C444\n";
+        assert!(y4m.starts_with(header));
+        let frame_size = b"FRAME\n".len() + 3 * SCREEN_WIDTH * SCREEN_HEIGHT;
+        assert_eq!(y4m.len(), header.len() + 2 * frame_size);
+        // The darkest shade is near black, the lightest near white
+        let second_frame = header.len() + frame_size + b"FRAME\n".len();
+        assert!(y4m[second_frame] < 40);
+        assert!(y4m[second_frame + 1] > 200);
+
+        let path = dir.join("test.gif");
+        let mut recorder = Recorder::start(&path, &DEFAULT_PALETTE).unwrap();
+        for _ in 0..3 {
+            recorder.record_frame(&frame, &DEFAULT_PALETTE).unwrap();
+        }
+        assert_eq!(recorder.finish().unwrap(), 3);
+        let gif = std::fs::read(&path).unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+
+        assert!(Recorder::start(&dir.join("test.mp4"), &DEFAULT_PALETTE).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,146 @@
+//! Golden-image regression tests. Each test runs a ROM headlessly for a fixed number of frames,
+//! then compares the visible 160x144 frame against a reference PNG. On a mismatch, the actual
+//! frame and a diff image (mismatched pixels in red) are written to `target/regression/`.
+//!
+//! Test ROMs and their reference images are not checked into the repo, so these tests are
+//! ignored by default. Run them with `cargo test -- --ignored` once the files are in place.
+
+use std::path::Path;
+
+use crate::{
+    cli::DMG_ACID_PATH,
+    create_gameboy_components, emulate_boot, tick_gameboy,
+    image::{RgbImage, load_png, save_png},
+    ppu::{GbFrame, SCREEN_HEIGHT, SCREEN_WIDTH, T_CYCLES_PER_FRAME},
+};
+
+const REGRESSION_OUTPUT_PATH: &str = "./target/regression/";
+
+/// Shade values used by the reference images, from color index 0 (lightest) to 3 (darkest)
+const REFERENCE_SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+const DIFF_COLOR: [u8; 3] = [0xFF, 0x00, 0x00];
+
+struct GoldenImage {
+    name: &'static str,
+    rom_path: &'static str,
+    reference_path: &'static str,
+    frames: u32,
+}
+
+const DMG_ACID2: GoldenImage = GoldenImage {
+    name: "dmg-acid2",
+    rom_path: DMG_ACID_PATH,
+    reference_path: "./test-roms/reference/dmg-acid2.png",
+    frames: 60,
+};
+
+fn run_headless(rom_path: &str, frames: u32) -> Option<GbFrame> {
+    let (mmu, mut cpu, mut ppu) = create_gameboy_components();
+
+    if !mmu.borrow_mut().load_rom(rom_path) {
+        return None;
+    }
+
+    emulate_boot(&mmu, &mut cpu);
+
+    for _i in 0..frames * T_CYCLES_PER_FRAME {
+        tick_gameboy(&mut cpu, &mmu, &mut ppu);
+    }
+
+    ppu.splat_tiles();
+    Some(ppu.get_frame())
+}
+
+fn frame_to_image(frame: &GbFrame) -> RgbImage {
+    let mut image = RgbImage::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    for (y, row) in frame.iter().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            let shade = REFERENCE_SHADES[*pixel as usize];
+            image.set(x, y, [shade, shade, shade]);
+        }
+    }
+    image
+}
+
+/// Reference images aren't always pure greyscale, so match each pixel to the nearest shade.
+fn nearest_color_index(rgb: [u8; 3]) -> u8 {
+    let luminance = (rgb[0] as u32 + rgb[1] as u32 + rgb[2] as u32) / 3;
+    let mut best_index = 0;
+    for (index, shade) in REFERENCE_SHADES.iter().enumerate() {
+        let distance = luminance.abs_diff(*shade as u32);
+        let best_distance = luminance.abs_diff(REFERENCE_SHADES[best_index] as u32);
+        if distance < best_distance {
+            best_index = index;
+        }
+    }
+    best_index as u8
+}
+
+/// Returns the number of mismatched pixels, along with an image highlighting them.
+fn compare_frame(frame: &GbFrame, reference: &RgbImage) -> (usize, RgbImage) {
+    let mut mismatches = 0;
+    let mut diff = frame_to_image(frame);
+
+    for (y, row) in frame.iter().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            let in_bounds = x < reference.width && y < reference.height;
+            if !in_bounds || nearest_color_index(reference.get(x, y)) != *pixel {
+                mismatches += 1;
+                diff.set(x, y, DIFF_COLOR);
+            }
+        }
+    }
+
+    (mismatches, diff)
+}
+
+fn check_golden_image(test: &GoldenImage) {
+    let reference_path = Path::new(test.reference_path);
+    if !Path::new(test.rom_path).exists() || !reference_path.exists() {
+        panic!(
+            "Golden image test \"{}\" needs \"{}\" and \"{}\"",
+            test.name, test.rom_path, test.reference_path
+        );
+    }
+
+    let frame = run_headless(test.rom_path, test.frames).expect("failed to load test rom");
+    let reference = load_png(reference_path).expect("failed to load reference image");
+    let (mismatches, diff) = compare_frame(&frame, &reference);
+
+    if mismatches != 0 {
+        let output_path = Path::new(REGRESSION_OUTPUT_PATH);
+        let actual_path = output_path.join(format!("{}-actual.png", test.name));
+        let diff_path = output_path.join(format!("{}-diff.png", test.name));
+        save_png(&actual_path, &frame_to_image(&frame)).unwrap();
+        save_png(&diff_path, &diff).unwrap();
+
+        panic!(
+            "\"{}\" differs from its reference in {} pixels. See {:?}",
+            test.name, mismatches, diff_path
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs ./test-roms/dmg-acid2.gb and its reference image"]
+fn test_dmg_acid2() {
+    check_golden_image(&DMG_ACID2);
+}
+
+#[test]
+fn test_compare_frame() {
+    let mut frame: GbFrame = [[0; SCREEN_WIDTH]; SCREEN_HEIGHT];
+    frame[10][20] = 3;
+    let reference = frame_to_image(&frame);
+
+    let (mismatches, _) = compare_frame(&frame, &reference);
+    assert_eq!(mismatches, 0);
+
+    frame[0][0] = 1;
+    frame[143][159] = 2;
+    let (mismatches, diff) = compare_frame(&frame, &reference);
+    assert_eq!(mismatches, 2);
+    assert_eq!(diff.get(0, 0), DIFF_COLOR);
+    assert_eq!(diff.get(159, 143), DIFF_COLOR);
+    assert_ne!(diff.get(20, 10), DIFF_COLOR);
+}
@@ -0,0 +1,87 @@
+//! Screenshots are saved as PNGs of the visible 160x144 frame, in the palette the window is
+//! using. They can be scaled up by a whole number, which keeps the pixels sharp.
+//!
+//! The hotkey (F12) saves to `screenshots/<rom name>-001.png`, counting up past any that are
+//! already there. The debugger's `screenshot <file> [scale]` saves wherever it's told.
+
+use crate::image::{RgbImage, save_png};
+use crate::ppu::{GbFrame, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::palette::Palette;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+const SCREENSHOT_DIR: &str = "screenshots";
+
+pub fn frame_to_image(frame: &GbFrame, palette: &Palette, scale: usize) -> RgbImage {
+    let mut image = RgbImage::new(SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let shade = frame[y / scale][x / scale];
+            image.set(x, y, palette[shade as usize]);
+        }
+    }
+    image
+}
+
+pub fn save_screenshot(
+    path: &Path,
+    frame: &GbFrame,
+    palette: &Palette,
+    scale: usize,
+) -> io::Result<()> {
+    save_png(path, &frame_to_image(frame, palette, scale))
+}
+
+/// Saves to the next free path for the ROM, and says where it went.
+pub fn take_screenshot(rom_path: &str, frame: &GbFrame, palette: &Palette, scale: usize) {
+    let path = next_free_path(SCREENSHOT_DIR, rom_path, "png");
+    match save_screenshot(&path, frame, palette, scale) {
+        Ok(()) => println!("Saved screenshot to \"{}\"", path.display()),
+        Err(error) => println!(
+            "Failed to save screenshot to \"{}\": {}",
+            path.display(),
+            error
+        ),
+    }
+}
+
+/// The first of `<dir>/<rom name>-001.<extension>`, `-002`, ... that doesn't exist yet.
+pub fn next_free_path(dir: &str, rom_path: &str, extension: &str) -> PathBuf {
+    let name = Path::new(rom_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "capture".to_string());
+
+    (1..)
+        .map(|number| Path::new(dir).join(format!("{}-{:03}.{}", name, number, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::DEFAULT_PALETTE;
+
+    #[test]
+    fn test_frame_to_image() {
+        let mut frame: GbFrame = [[0; SCREEN_WIDTH]; SCREEN_HEIGHT];
+        frame[0][1] = 3;
+        frame[143][159] = 2;
+
+        let image = frame_to_image(&frame, &DEFAULT_PALETTE, 1);
+        assert_eq!((image.width, image.height), (160, 144));
+        assert_eq!(image.get(0, 0), DEFAULT_PALETTE[0]);
+        assert_eq!(image.get(1, 0), DEFAULT_PALETTE[3]);
+        assert_eq!(image.get(159, 143), DEFAULT_PALETTE[2]);
+
+        let image = frame_to_image(&frame, &DEFAULT_PALETTE, 3);
+        assert_eq!((image.width, image.height), (480, 432));
+        assert_eq!(image.get(2, 2), DEFAULT_PALETTE[0]);
+        assert_eq!(image.get(3, 0), DEFAULT_PALETTE[3]);
+        assert_eq!(image.get(5, 2), DEFAULT_PALETTE[3]);
+        assert_eq!(image.get(479, 431), DEFAULT_PALETTE[2]);
+    }
+}
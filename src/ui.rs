@@ -1,13 +1,30 @@
-use crate::ppu::GbDisplay;
+//! The window shows the visible 160x144 frame through a streaming texture, which is scaled up
+//! by whole numbers to fit the window, and centered with black bars around it. The window can be
+//! resized, and F11 toggles fullscreen.
+//!
+//! Before that, the display can go through one of the upscalers in `filters::upscale`, on the
+//! CPU, and F7 cycles through them.
+
+use crate::cli::Options;
+use crate::filters::ghosting::{DEFAULT_PERSISTENCE, FrameBlender};
+use crate::filters::upscale::Upscaler;
+use crate::image::RgbImage;
+use crate::palette::{Palette, next_preset, palette_from_options};
+use crate::ppu::{GbFrame, SCREEN_HEIGHT, SCREEN_WIDTH};
 
 use sdl2::{
-    EventPump, event::Event, keyboard::Scancode, pixels::Color, rect::Rect, render::Canvas,
-    video::Window,
+    EventPump,
+    event::Event,
+    keyboard::Scancode,
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{Canvas, Texture},
+    video::{FullscreenType, Window},
 };
 
-pub const WINDOW_WIDTH: usize = 256;
-pub const WINDOW_HEIGHT: usize = 256;
-pub const WINDOW_SCALE_FACTOR: usize = 2;
+/// How much the window starts scaled up by, without `--scale`
+pub const DEFAULT_SCALE: usize = 3;
+const BYTES_PER_PIXEL: usize = 3;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Inputs {
@@ -17,14 +34,23 @@ pub struct Inputs {
     pub d: bool,
     pub m: bool,
     pub n: bool,
+    pub enter: bool,
+    pub backspace: bool,
 
     pub g: bool,
     pub r: bool,
     pub p: bool,
+    pub tab: bool,
+    pub f7: bool,
+    pub f8: bool,
+    pub f9: bool,
+    pub f10: bool,
+    pub f11: bool,
+    pub f12: bool,
 }
 
 impl Inputs {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Inputs {
             w: false,
             a: false,
@@ -32,10 +58,19 @@ impl Inputs {
             d: false,
             m: false,
             n: false,
+            enter: false,
+            backspace: false,
 
             g: false,
             r: false,
             p: false,
+            tab: false,
+            f7: false,
+            f8: false,
+            f9: false,
+            f10: false,
+            f11: false,
+            f12: false,
         }
     }
 
@@ -48,22 +83,43 @@ impl Inputs {
             Scancode::D => self.d,
             Scancode::M => self.m,
             Scancode::N => self.n,
+            Scancode::Return => self.enter,
+            Scancode::Backspace => self.backspace,
 
             Scancode::G => self.g,
             Scancode::R => self.r,
             Scancode::P => self.p,
+            Scancode::Tab => self.tab,
+            Scancode::F7 => self.f7,
+            Scancode::F8 => self.f8,
+            Scancode::F9 => self.f9,
+            Scancode::F10 => self.f10,
+            Scancode::F11 => self.f11,
+            Scancode::F12 => self.f12,
             _ => false,
         }
     }
 
     fn set(&mut self, scancode: Scancode, set: bool) {
         match scancode {
+            Scancode::W => self.w = set,
             Scancode::A => self.a = set,
-            Scancode::G => self.g = set,
+            Scancode::S => self.s = set,
+            Scancode::D => self.d = set,
             Scancode::M => self.m = set,
             Scancode::N => self.n = set,
+            Scancode::Return => self.enter = set,
+            Scancode::Backspace => self.backspace = set,
+            Scancode::G => self.g = set,
             Scancode::R => self.r = set,
             Scancode::P => self.p = set,
+            Scancode::Tab => self.tab = set,
+            Scancode::F7 => self.f7 = set,
+            Scancode::F8 => self.f8 = set,
+            Scancode::F9 => self.f9 = set,
+            Scancode::F10 => self.f10 = set,
+            Scancode::F11 => self.f11 = set,
+            Scancode::F12 => self.f12 = set,
             _ => (),
         };
     }
@@ -75,72 +131,161 @@ pub struct UserInterface {
     pub inputs_unique: Inputs,
 
     canvas: Canvas<Window>,
+    /// All of the screens side by side, upscaled. It's freed along with the canvas.
+    texture: Texture,
+    /// What each screen shows: the last frame in the palette, blended with the ones before it
+    screens: Vec<RgbImage>,
+    upscaler: Upscaler,
+    blenders: Vec<FrameBlender>,
+    blend_frames: bool,
     event_pump: EventPump,
     pub running: bool,
+    pub palette: Palette,
 }
 
 impl UserInterface {
-    pub fn new() -> Self {
-        let (canvas, event_pump) = UserInterface::init_window();
+    pub fn new(options: &Options) -> Self {
+        UserInterface::with_screens(1, options)
+    }
+
+    /// The screens are laid out side by side, e.g. for two linked Game Boys.
+    pub fn with_screens(screens: usize, options: &Options) -> Self {
+        let upscaler = options.filter.unwrap_or(Upscaler::Nearest);
+        // Big enough to show the upscaler's pixels without shrinking them
+        let scale = options
+            .scale
+            .unwrap_or(DEFAULT_SCALE.max(upscaler.factor()));
+        let (canvas, texture, event_pump) = UserInterface::init_window(screens, scale);
+        let persistence = options.ghosting.unwrap_or(DEFAULT_PERSISTENCE);
         UserInterface {
             canvas,
+            texture,
+            screens: (0..screens)
+                .map(|_| RgbImage::new(SCREEN_WIDTH, SCREEN_HEIGHT))
+                .collect(),
+            upscaler,
+            blenders: (0..screens)
+                .map(|_| FrameBlender::new(persistence))
+                .collect(),
+            blend_frames: options
+                .ghosting
+                .is_some_and(|persistence| persistence > 0.0),
             event_pump,
             inputs_down: Inputs::new(),
             inputs_was_down: Inputs::new(),
             inputs_unique: Inputs::new(),
             running: true,
+            palette: palette_from_options(options),
         }
     }
 
-    fn init_window() -> (Canvas<Window>, EventPump) {
+    fn init_window(screens: usize, scale: usize) -> (Canvas<Window>, Texture, EventPump) {
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
 
         let window = video_subsystem
             .window(
                 "Gameboy",
-                (WINDOW_WIDTH * WINDOW_SCALE_FACTOR) as u32,
-                (WINDOW_HEIGHT * WINDOW_SCALE_FACTOR) as u32,
+                (screens * SCREEN_WIDTH * scale) as u32,
+                (SCREEN_HEIGHT * scale) as u32,
             )
             .position_centered()
+            .resizable()
             .build()
             .unwrap();
 
         // Rendering
         let canvas = window.into_canvas().build().unwrap();
+        let texture = canvas
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                (screens * SCREEN_WIDTH) as u32,
+                SCREEN_HEIGHT as u32,
+            )
+            .unwrap();
 
         // Window events
         let event_pump = sdl_context.event_pump().unwrap();
 
-        (canvas, event_pump)
+        (canvas, texture, event_pump)
+    }
+
+    /// Draws a frame the Game Boy has finished onto its screen. This is called once for each
+    /// emulated frame (see `Ppu::take_frame_ready`), so that blending keeps in step with them.
+    pub fn draw_frame(&mut self, screen: usize, frame: &GbFrame) {
+        let image = &mut self.screens[screen];
+        draw_frame(image, frame, &self.palette);
+        if self.blend_frames {
+            self.blenders[screen].blend(&mut image.pixels);
+        }
     }
 
-    pub fn render_display(&mut self, display: &GbDisplay) {
+    /// Shows the screens in the window, side by side and upscaled.
+    pub fn present(&mut self) {
+        let factor = self.upscaler.factor();
+        let (screen_width, screen_height) = (SCREEN_WIDTH * factor, SCREEN_HEIGHT * factor);
+        let texture_size = (
+            (self.screens.len() * screen_width) as u32,
+            screen_height as u32,
+        );
+
+        let query = self.texture.query();
+        if (query.width, query.height) != texture_size {
+            let (width, height) = texture_size;
+            let texture = self
+                .canvas
+                .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+                .unwrap();
+            let old_texture = std::mem::replace(&mut self.texture, texture);
+            // Safe because the canvas it came from is still alive
+            unsafe { old_texture.destroy() };
+        }
+
+        for (index, screen) in self.screens.iter().enumerate() {
+            let upscaled;
+            let image = match self.upscaler {
+                Upscaler::Nearest => screen,
+                upscaler => {
+                    upscaled = upscaler.upscale(screen);
+                    &upscaled
+                }
+            };
+            let x = (index * screen_width) as i32;
+            let rect = Rect::new(x, 0, screen_width as u32, screen_height as u32);
+            let pitch = screen_width * BYTES_PER_PIXEL;
+            self.texture.update(rect, &image.pixels, pitch).unwrap();
+        }
+
+        let (x, y, width, height) = letterbox(self.canvas.output_size().unwrap(), texture_size);
+
         self.canvas.set_draw_color(Color::RGB(0, 0, 0));
         self.canvas.clear();
-        for (y, row) in display.iter().enumerate() {
-            for (x, pixel) in row.iter().enumerate() {
-                let color = match pixel {
-                    3 => Color::RGB(8, 24, 32),
-                    2 => Color::RGB(52, 104, 86),
-                    1 => Color::RGB(136, 192, 112),
-                    0 => Color::RGB(224, 248, 208),
-                    _ => panic!("Invalid pixel color value detected in the display"),
-                };
-                self.canvas.set_draw_color(color);
-
-                self.canvas
-                    .fill_rect(Rect::new(
-                        (x as i32) * (WINDOW_SCALE_FACTOR as i32),
-                        (y as i32) * (WINDOW_SCALE_FACTOR as i32),
-                        WINDOW_SCALE_FACTOR as u32,
-                        WINDOW_SCALE_FACTOR as u32,
-                    ))
-                    .unwrap();
-            }
+        self.canvas
+            .copy(&self.texture, None, Rect::new(x, y, width, height))
+            .unwrap();
+        self.canvas.present();
+    }
+
+    fn toggle_frame_blending(&mut self) {
+        self.blend_frames = !self.blend_frames;
+        self.blenders.iter_mut().for_each(FrameBlender::reset);
+        if self.blend_frames {
+            let persistence = self.blenders[0].persistence();
+            println!("Frame blending on (persistence {})", persistence);
+        } else {
+            println!("Frame blending off");
         }
+    }
 
-        self.canvas.present();
+    fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        if let Err(error) = window.set_fullscreen(fullscreen) {
+            println!("Failed to toggle fullscreen: {}", error);
+        }
     }
 
     pub fn process_inputs(&mut self) {
@@ -172,14 +317,99 @@ impl UserInterface {
             Scancode::D,
             Scancode::M,
             Scancode::N,
+            Scancode::Return,
+            Scancode::Backspace,
             Scancode::G,
             Scancode::R,
             Scancode::P,
+            Scancode::Tab,
+            Scancode::F7,
+            Scancode::F8,
+            Scancode::F9,
+            Scancode::F10,
+            Scancode::F11,
+            Scancode::F12,
         ] {
             let a_down = self.inputs_down.get(scancode);
             let a_was_down = self.inputs_was_down.get(scancode);
             let a_unique = a_down && !a_was_down;
             self.inputs_unique.set(scancode, a_unique);
         }
+
+        if self.inputs_unique.f7 {
+            self.upscaler = self.upscaler.next();
+            println!("Filter: {}", self.upscaler.name());
+        }
+        if self.inputs_unique.f8 {
+            self.toggle_frame_blending();
+        }
+        if self.inputs_unique.f11 {
+            self.toggle_fullscreen();
+        }
+        if self.inputs_unique.f9 {
+            let (name, palette) = next_preset(&self.palette);
+            self.palette = palette;
+            println!("Palette: {}", name);
+        }
+    }
+}
+
+/// Writes a frame's RGB pixels into a 160x144 image.
+fn draw_frame(image: &mut RgbImage, frame: &GbFrame, palette: &Palette) {
+    for (y, row) in frame.iter().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            image.set(x, y, palette[(*pixel & 0b11) as usize]);
+        }
+    }
+}
+
+/// Where the image goes in the window (x, y, width and height): as big as it can be while
+/// scaled up by a whole number, and centered. Windows that are too small to fit it at all get
+/// it shrunk to fit instead, keeping its shape.
+fn letterbox(output: (u32, u32), image: (u32, u32)) -> (i32, i32, u32, u32) {
+    let (output_width, output_height) = output;
+    let (image_width, image_height) = image;
+
+    let scale = (output_width / image_width).min(output_height / image_height);
+    let (width, height) = if scale > 0 {
+        (image_width * scale, image_height * scale)
+    } else if output_width * image_height < output_height * image_width {
+        (output_width, image_height * output_width / image_width)
+    } else {
+        (image_width * output_height / image_height, output_height)
+    };
+
+    let x = (output_width - width) / 2;
+    let y = (output_height - height) / 2;
+    (x as i32, y as i32, width.max(1), height.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_letterbox() {
+        assert_eq!(letterbox((480, 432), (160, 144)), (0, 0, 480, 432));
+        // Only whole number scales, centered
+        assert_eq!(letterbox((800, 600), (160, 144)), (80, 12, 640, 576));
+        assert_eq!(letterbox((1920, 1080), (320, 144)), (0, 108, 1920, 864));
+        // Too small for even 1x
+        assert_eq!(letterbox((80, 200), (160, 144)), (0, 64, 80, 72));
+    }
+
+    #[test]
+    fn test_draw_frame() {
+        let palette = crate::palette::DEFAULT_PALETTE;
+        let mut frame: GbFrame = [[0; SCREEN_WIDTH]; SCREEN_HEIGHT];
+        frame[1][2] = 3;
+
+        frame[143][159] = 6; // Only the 2 low bits are used
+
+        let mut image = RgbImage::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+        draw_frame(&mut image, &frame, &palette);
+        assert_eq!(image.get(2, 1), palette[3]);
+        assert_eq!(image.get(1, 1), palette[0]);
+        assert_eq!(image.get(159, 143), palette[2]);
     }
 }